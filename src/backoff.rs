@@ -0,0 +1,54 @@
+//! Exchange re-connect delay policy.
+
+use std::time::Duration;
+
+/// Exponential backoff with jitter.
+///
+/// Delays double with each consecutive attempt up to `max`, with a random
+/// 50-100% of each delay used so many clients don't re-connect in lock step.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first re-connect attempt.
+    pub initial: Duration,
+    /// Maximum delay between attempts.
+    pub max: Duration,
+    /// Consecutive failed attempts after which to give up, `None` to retry forever.
+    pub max_attempts: Option<u32>,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, max_attempts: Option<u32>) -> Self {
+        Self {
+            initial,
+            max,
+            max_attempts,
+            attempts: 0,
+        }
+    }
+
+    /// Returns the delay before the next attempt, or `None` if attempts are exhausted.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| self.attempts >= max) {
+            return None;
+        }
+        let delay = self
+            .initial
+            .saturating_mul(2_u32.saturating_pow(self.attempts))
+            .min(self.max);
+        self.attempts += 1;
+
+        let half = delay / 2;
+        Some(half + half.mul_f64(rand::random()))
+    }
+
+    /// Number of attempts since the last reset.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Resets the delay after a successful connection.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
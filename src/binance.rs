@@ -1,100 +1,240 @@
 //! binance exchange.
 
-use anyhow::Context;
-use futures_util::StreamExt;
-use std::{env, time::Duration};
-use tokio_tungstenite::tungstenite::Message;
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    snapshot::SnapshotClient,
+    summary::{ExchangeInfo, Summary},
+    symbol::Symbol,
+};
+use std::{
+    env,
+    time::{Duration, UNIX_EPOCH},
+};
 
 const EXCHANGE_NAME: &str = "binance";
 
+/// Binance book depth stream.
+///
+/// Uses partial book depth streams by default, or if `BINANCE_DIFF_DEPTH` is set
+/// (or depth is over 20) maintains a full local book from the diff depth stream.
 #[derive(Debug)]
-pub struct BinanceClient {
-    pub tx: tokio::sync::broadcast::Sender<merged_order_book_protos::Summary>,
+pub struct Binance {
+    pub symbol: Symbol,
+    /// Bids/asks depth, partial book streams provide at most 20.
+    pub depth: usize,
 }
 
-impl BinanceClient {
-    /// Connects to bitstamp order book stream.
-    ///
-    /// Initial future resolves once the first summary has been received.
-    pub async fn start(symbol: &str) -> anyhow::Result<Self> {
-        let url = env::var("BINANCE_URL")
-            .unwrap_or_else(|_| "wss://stream.binance.com:9443".into())
+impl Binance {
+    fn diff_depth(&self) -> bool {
+        env::var("BINANCE_DIFF_DEPTH")
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(self.depth > 20)
+    }
+}
+
+impl Exchange for Binance {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        let stream = match self.diff_depth() {
+            true => "@depth@100ms".into(),
+            false => format!("@depth{}@100ms", partial_depth(self.depth)),
+        };
+        env::var("BINANCE_URL").unwrap_or_else(|_| "wss://stream.binance.com:9443".into())
             + "/ws/"
-            + symbol
-            + "@depth10@100ms";
-
-        let (tx, _) = tokio::sync::broadcast::channel(1);
-        let tx2 = tx.clone();
-
-        let (connected_tx, connected_rx) = tokio::sync::oneshot::channel();
-
-        tokio::spawn(async move {
-            let mut connected = Some(connected_tx);
-            loop {
-                let (_, mut ws_read) = match tokio_tungstenite::connect_async(&url).await {
-                    Ok((stream, _)) => stream.split(),
-                    Err(err) => {
-                        eprintln!("{url}: {err}");
-                        tokio::time::sleep(Duration::from_secs(1)).await;
-                        continue;
-                    }
-                };
-
-                while let Some(msg) = ws_read.next().await {
-                    let Ok(Message::Text(json)) = msg else { continue };
-                    let Ok(msg) = serde_json::from_str::<DepthMessage>(&json) else { continue };
-                    match msg.try_into() {
-                        Ok(summary) => {
-                            _ = tx2.send(summary);
-                            connected.take().map(|tx| tx.send(()));
-                        }
-                        Err(_) => {
-                            eprintln!("Invalid binance message format `{json}`");
-                        }
-                    }
-                }
-            }
-        });
+            + &self.symbol.concat_lower()
+            + &stream
+    }
 
-        tokio::time::timeout(Duration::from_secs(12), connected_rx)
-            .await
-            .context("Initial binance connection failed")??;
+    fn decoder(&self) -> Box<dyn Decoder> {
+        match self.diff_depth() {
+            true => Box::new(DiffDepthDecoder {
+                snapshot_url: format!(
+                    "{}/api/v3/depth?symbol={}&limit={}",
+                    env::var("BINANCE_REST_URL")
+                        .unwrap_or_else(|_| "https://api.binance.com".into()),
+                    self.symbol.concat_lower().to_ascii_uppercase(),
+                    if self.depth > 1000 { 5000 } else { 1000 },
+                ),
+                depth: self.depth,
+                snapshots: SnapshotClient::new(EXCHANGE_NAME),
+                book: <_>::default(),
+                last_update_id: None,
+            }),
+            false => Box::new(PartialDepthDecoder { depth: self.depth }),
+        }
+    }
 
-        eprintln!("Binance connected");
+    /// Connections are closed by binance after 24 hours, so are replaced before then.
+    fn rotate_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(23 * 60 * 60))
+    }
+}
 
-        Ok(Self { tx })
+/// Returns the smallest valid partial book depth stream level satisfying `depth`.
+fn partial_depth(depth: usize) -> usize {
+    match depth {
+        0..=5 => 5,
+        6..=10 => 10,
+        _ => 20,
     }
 }
 
-/// Top 10 bids/asks.
-#[derive(Debug, serde::Deserialize)]
-struct DepthMessage {
-    pub bids: Vec<[String; 2]>,
-    pub asks: Vec<[String; 2]>,
+/// Decodes partial book depth stream messages.
+#[derive(Debug)]
+struct PartialDepthDecoder {
+    depth: usize,
 }
 
-impl TryFrom<DepthMessage> for merged_order_book_protos::Summary {
-    type Error = ();
-
-    fn try_from(msg: DepthMessage) -> Result<Self, Self::Error> {
-        let mut summary = Self {
-            bids: msg
-                .bids
-                .iter()
-                .map(|b| (EXCHANGE_NAME, b).try_into())
-                .collect::<Result<_, _>>()?,
-            asks: msg
-                .asks
-                .iter()
-                .map(|b| (EXCHANGE_NAME, b).try_into())
-                .collect::<Result<_, _>>()?,
-            ..<_>::default()
+#[async_trait::async_trait]
+impl Decoder for PartialDepthDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(mut msg) = serde_json::from_str::<DepthMessage>(json) else {
+            return Ok(decode_event(json));
+        };
+        msg.bids.truncate(self.depth);
+        msg.asks.truncate(self.depth);
+        let info = ExchangeInfo {
+            sequence: msg.last_update_id,
+            ..ExchangeInfo::new(EXCHANGE_NAME)
         };
+        Summary::parse(EXCHANGE_NAME, &msg.bids, &msg.asks)
+            .map(|s| Some(Decoded::Summary(s.with_info(info))))
+    }
+}
+
+/// Maintains a local book from diff depth stream messages & REST snapshots.
+///
+/// See <https://binance-docs.github.io/apidocs/spot/en/#how-to-manage-a-local-order-book-correctly>.
+#[derive(Debug)]
+struct DiffDepthDecoder {
+    snapshot_url: String,
+    depth: usize,
+    snapshots: SnapshotClient,
+    book: LocalBook,
+    /// Last applied update id, `None` if a new snapshot is required.
+    last_update_id: Option<u64>,
+}
+
+impl DiffDepthDecoder {
+    /// Fetches a REST snapshot replacing the local book.
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        let snapshot: DepthMessage = self.snapshots.get(&self.snapshot_url).await?;
+        let last_update_id = snapshot
+            .last_update_id
+            .ok_or_else(|| anyhow::anyhow!("Snapshot missing lastUpdateId"))?;
+
+        self.book.clear();
+        let updated = self
+            .book
+            .update_bids(&snapshot.bids)
+            .and_then(|_| self.book.update_asks(&snapshot.asks));
+        anyhow::ensure!(updated.is_ok(), "Invalid snapshot levels");
+
+        self.last_update_id = Some(last_update_id);
+        Ok(())
+    }
+}
 
-        if !summary.bids.is_empty() && !summary.asks.is_empty() {
-            summary.spread = summary.asks[0].price - summary.bids[0].price;
+#[async_trait::async_trait]
+impl Decoder for DiffDepthDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(update) = serde_json::from_str::<DepthUpdate>(json) else {
+            return Ok(decode_event(json));
+        };
+
+        let last_update_id = match self.last_update_id {
+            Some(id) => id,
+            // updates are buffered by the websocket while fetching the snapshot
+            None if self.snapshots.throttled() => return Ok(None),
+            None => match self.sync().await {
+                Ok(_) => {
+                    self.snapshots.reset();
+                    self.last_update_id.unwrap_or_default()
+                }
+                Err(err) => {
+                    let retry = self.snapshots.failed();
+                    tracing::warn!(?retry, "snapshot {err}");
+                    return Ok(None);
+                }
+            },
+        };
+
+        if update.last_update_id <= last_update_id {
+            // already included in the local book
+            return Ok(None);
+        }
+        if update.first_update_id > last_update_id + 1 {
+            tracing::warn!("depth update gap, resyncing");
+            self.last_update_id = None;
+            return Ok(None);
         }
 
-        Ok(summary)
+        let updated = self
+            .book
+            .update_bids(&update.bids)
+            .and_then(|_| self.book.update_asks(&update.asks));
+        if updated.is_err() {
+            self.last_update_id = None;
+            return Err(());
+        }
+        self.last_update_id = Some(update.last_update_id);
+
+        let info = ExchangeInfo {
+            event_time: Some(UNIX_EPOCH + Duration::from_millis(update.event_time)),
+            sequence: Some(update.last_update_id),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
     }
 }
+
+/// Decodes non-depth events, a `serverShutdown` event requests a re-connect.
+fn decode_event(json: &str) -> Option<Decoded> {
+    let event: Event = serde_json::from_str(json).ok()?;
+    match event.event_type.as_str() {
+        "serverShutdown" => Some(Decoded::Reconnect),
+        _ => None,
+    }
+}
+
+/// Generic event.
+#[derive(Debug, serde::Deserialize)]
+struct Event {
+    #[serde(rename = "e")]
+    pub event_type: String,
+}
+
+/// Top 5, 10 or 20 bids/asks, or a REST snapshot.
+#[derive(Debug, serde::Deserialize)]
+struct DepthMessage {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: Option<u64>,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// Diff depth stream update.
+#[derive(Debug, serde::Deserialize)]
+struct DepthUpdate {
+    /// Event time, unix milliseconds.
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub last_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    pub asks: Vec<[String; 2]>,
+}
@@ -0,0 +1,270 @@
+//! bitfinex exchange.
+
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    summary::{ExchangeInfo, Summary},
+    symbol::Symbol,
+};
+use rust_decimal::Decimal;
+use serde_json::value::RawValue;
+use std::env;
+
+const EXCHANGE_NAME: &str = "bitfinex";
+
+/// Levels included in the book checksum.
+const CHECKSUM_DEPTH: usize = 25;
+
+/// `OB_CHECKSUM` conf flag, enabling checksum messages.
+const OB_CHECKSUM: u32 = 131_072;
+
+/// Bitfinex P0 aggregated book channel.
+///
+/// Maintains a local book from the snapshot & level updates, verifying
+/// `OB_CHECKSUM` messages & re-connecting on mismatch.
+#[derive(Debug)]
+pub struct Bitfinex {
+    pub symbol: Symbol,
+    /// Bids/asks depth, the book channel provides at most 250.
+    pub depth: usize,
+}
+
+impl Bitfinex {
+    /// Bitfinex trading pair symbol, e.g. `tETHBTC`, `tBTCUST` or `tSOLUDC`.
+    ///
+    /// Currencies longer than 3 characters are separated with a `:`.
+    fn pair_symbol(&self) -> String {
+        let base = currency_code(&self.symbol.base);
+        let quote = currency_code(&self.symbol.quote);
+        match base.len() > 3 || quote.len() > 3 {
+            true => format!("t{base}:{quote}"),
+            false => format!("t{base}{quote}"),
+        }
+    }
+}
+
+/// Bitfinex currency code, e.g. `UST` for `usdt`.
+fn currency_code(currency: &str) -> String {
+    match currency.to_ascii_uppercase().as_str() {
+        "USDT" => "UST".into(),
+        "USDC" => "UDC".into(),
+        code => code.into(),
+    }
+}
+
+impl Exchange for Bitfinex {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("BITFINEX_URL").unwrap_or_else(|_| "wss://api-pub.bitfinex.com/ws/2".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![
+            serde_json::json!({"event": "conf", "flags": OB_CHECKSUM}).to_string(),
+            serde_json::json!({
+                "event": "subscribe",
+                "channel": "book",
+                "symbol": self.pair_symbol(),
+                "prec": "P0",
+                "freq": "F0",
+                "len": book_len(self.depth).to_string(),
+            })
+            .to_string(),
+        ]
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(BookDecoder {
+            depth: self.depth,
+            book_len: book_len(self.depth),
+            book: <_>::default(),
+            chan_id: None,
+            synced: false,
+        })
+    }
+}
+
+/// Returns the smallest valid book length satisfying `depth`.
+fn book_len(depth: usize) -> usize {
+    match depth {
+        0..=25 => 25,
+        26..=100 => 100,
+        _ => 250,
+    }
+}
+
+/// Maintains a local book from book channel snapshots & updates.
+///
+/// Messages are positional arrays, e.g. `[CHAN_ID, [PRICE, COUNT, AMOUNT]]`, with a positive
+/// amount for bids & negative for asks.
+///
+/// See <https://docs.bitfinex.com/reference/ws-public-books>.
+#[derive(Debug)]
+struct BookDecoder {
+    depth: usize,
+    /// Subscribed book length, levels beyond this are not updated by bitfinex.
+    book_len: usize,
+    book: LocalBook,
+    /// Subscribed book channel id.
+    chan_id: Option<u64>,
+    /// Whether the local book is valid, `false` until a snapshot is applied.
+    synced: bool,
+}
+
+#[async_trait::async_trait]
+impl Decoder for BookDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let msg = match serde_json::from_str::<Vec<&RawValue>>(json) {
+            Ok(msg) => msg,
+            Err(_) => return Ok(self.decode_event(json)),
+        };
+        let (Some(chan_id), Some(payload)) = (msg.first(), msg.get(1)) else {
+            return Err(());
+        };
+        if chan_id.get().parse().ok() != self.chan_id {
+            return Ok(None);
+        }
+
+        match payload.get() {
+            r#""hb""# => return Ok(None), // heartbeat
+            r#""cs""# => {
+                let checksum: i32 = msg
+                    .get(2)
+                    .and_then(|cs| serde_json::from_str(cs.get()).ok())
+                    .ok_or(())?;
+                if !self.synced {
+                    return Ok(None);
+                }
+                let expected = book_checksum(&self.book.summary(EXCHANGE_NAME, CHECKSUM_DEPTH));
+                if checksum == expected {
+                    return Ok(None);
+                }
+                tracing::warn!(checksum, expected, "book checksum mismatch, re-connecting");
+                // unsubscribing requires the channel id, so use a new connection
+                self.book.clear();
+                self.synced = false;
+                return Ok(Some(Decoded::Reconnect));
+            }
+            _ => {}
+        }
+
+        let levels = match serde_json::from_str::<Vec<[&RawValue; 3]>>(payload.get()) {
+            Ok(snapshot) => {
+                self.book.clear();
+                self.synced = true;
+                snapshot
+            }
+            Err(_) if !self.synced => return Ok(None),
+            Err(_) => vec![serde_json::from_str(payload.get()).map_err(|_| ())?],
+        };
+
+        let mut bids = vec![];
+        let mut asks = vec![];
+        for [price, count, amount] in levels {
+            let price = decimal(price.get())?;
+            let amount = decimal(amount.get())?;
+            let level = match count.get() {
+                // count 0 removes the level, amount 1 for bids & -1 for asks
+                "0" => [price.to_string(), "0".into()],
+                _ => [price.to_string(), amount.abs().to_string()],
+            };
+            match amount.is_sign_negative() {
+                true => asks.push(level),
+                false => bids.push(level),
+            }
+        }
+        let updated = self
+            .book
+            .update_bids(&bids)
+            .and_then(|_| self.book.update_asks(&asks));
+        if updated.is_err() {
+            self.book.clear();
+            self.synced = false;
+            return Err(());
+        }
+        // levels pushed beyond the subscribed length are not removed by updates
+        self.book.truncate(self.book_len);
+
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(
+            summary.with_info(ExchangeInfo::new(EXCHANGE_NAME)),
+        )))
+    }
+}
+
+impl BookDecoder {
+    /// Decodes events, storing the subscribed channel id. An `error` event is a subscription error.
+    fn decode_event(&mut self, json: &str) -> Option<Decoded> {
+        let event: Event = serde_json::from_str(json).ok()?;
+        match event.event.as_str() {
+            "subscribed" if event.channel.as_deref() == Some("book") => {
+                self.chan_id = event.chan_id;
+                None
+            }
+            "error" => Some(Decoded::SubscriptionError(format!(
+                "{} (code {})",
+                event.msg.unwrap_or_default(),
+                event.code.unwrap_or_default()
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a json number, which may use exponent notation e.g. `1.5e-7`.
+fn decimal(number: &str) -> Result<Decimal, ()> {
+    number
+        .parse()
+        .or_else(|_| Decimal::from_scientific(number))
+        .map_err(|_| ())
+}
+
+/// Signed CRC32 of the top 25 bids & asks interleaved, `bid:amount:ask:-amount:...`.
+fn book_checksum(book: &Summary) -> i32 {
+    let mut values = vec![];
+    for idx in 0..CHECKSUM_DEPTH {
+        if let Some(bid) = book.bids.get(idx) {
+            values.push(js_string(bid.price));
+            values.push(js_string(bid.amount));
+        }
+        if let Some(ask) = book.asks.get(idx) {
+            values.push(js_string(ask.price));
+            values.push(js_string(-ask.amount));
+        }
+    }
+    crc32fast::hash(values.join(":").as_bytes()) as i32
+}
+
+/// Formats like javascript `Number.toString`, as used by bitfinex checksums,
+/// e.g. `0.0714` or `1.5e-7`.
+fn js_string(value: Decimal) -> String {
+    let value = value.normalize();
+    if value.is_zero() || value.abs() >= Decimal::new(1, 6) {
+        return value.to_string();
+    }
+    let digits = value.mantissa().unsigned_abs().to_string();
+    let exponent = digits.len() as i64 - 1 - i64::from(value.scale());
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    match digits.split_at(1) {
+        (first, "") => format!("{sign}{first}e{exponent}"),
+        (first, rest) => format!("{sign}{first}.{rest}e{exponent}"),
+    }
+}
+
+/// Event, e.g. `subscribed` or `error`.
+#[derive(Debug, serde::Deserialize)]
+struct Event {
+    pub event: String,
+    pub channel: Option<String>,
+    #[serde(rename = "chanId")]
+    pub chan_id: Option<u64>,
+    pub code: Option<u64>,
+    pub msg: Option<String>,
+}
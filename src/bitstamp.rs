@@ -1,77 +1,213 @@
 //! bitstamp exchange.
 
-use anyhow::Context;
-use futures_util::{SinkExt, StreamExt};
-use std::{env, time::Duration};
-use tokio_tungstenite::tungstenite::Message;
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    snapshot::SnapshotClient,
+    summary::{ExchangeInfo, Summary},
+    symbol::Symbol,
+};
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 const EXCHANGE_NAME: &str = "bitstamp";
 
+/// Bitstamp order book channel.
+///
+/// Uses the order book channel by default, or if `BITSTAMP_DIFF_ORDER_BOOK` is set
+/// (or depth is over 100) maintains a full local book from the diff order book channel.
 #[derive(Debug)]
-pub struct BitstampClient {
-    pub tx: tokio::sync::broadcast::Sender<merged_order_book_protos::Summary>,
+pub struct Bitstamp {
+    pub symbol: Symbol,
+    /// Bids/asks depth, order book channel provides at most 100.
+    pub depth: usize,
 }
 
-impl BitstampClient {
-    /// Connects to bitstamp order book stream.
-    ///
-    /// Initial future resolves once the first summary has been received.
-    pub async fn start(symbol: &str) -> anyhow::Result<Self> {
-        let url = env::var("BITSTAMP_URL").unwrap_or_else(|_| "wss://ws.bitstamp.net".into());
-        let sub_msg = r#"{"event":"bts:subscribe","data":{"channel":"order_book_"#.to_string()
-            + symbol
-            + r#""}}"#;
-
-        let (tx, _) = tokio::sync::broadcast::channel(1);
-        let tx2 = tx.clone();
-
-        let (connected_tx, connected_rx) = tokio::sync::oneshot::channel();
-
-        tokio::spawn(async move {
-            let mut connected = Some(connected_tx);
-            loop {
-                let (mut ws_write, mut ws_read) = match tokio_tungstenite::connect_async(&url).await
-                {
-                    Ok((stream, _)) => stream.split(),
-                    Err(err) => {
-                        eprintln!("{url}: {err}");
-                        tokio::time::sleep(Duration::from_secs(1)).await;
-                        continue;
-                    }
-                };
-
-                if let Err(err) = ws_write.send(Message::Text(sub_msg.clone())).await {
-                    eprintln!("bitstamp subscribe {err}");
-                    continue;
-                }
+impl Bitstamp {
+    fn diff_order_book(&self) -> bool {
+        env::var("BITSTAMP_DIFF_ORDER_BOOK")
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(self.depth > 100)
+    }
+}
+
+impl Exchange for Bitstamp {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("BITSTAMP_URL").unwrap_or_else(|_| "wss://ws.bitstamp.net".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        let channel = match self.diff_order_book() {
+            true => "diff_order_book_",
+            false => "order_book_",
+        };
+        vec![
+            r#"{"event":"bts:subscribe","data":{"channel":""#.to_string()
+                + channel
+                + &self.symbol.concat_lower()
+                + r#""}}"#,
+        ]
+    }
 
-                while let Some(msg) = ws_read.next().await {
-                    let Ok(Message::Text(json)) = msg else { continue };
-                    let Ok(val) = serde_json::from_str::<serde_json::Value>(&json) else { continue };
-                    if val["event"] != "data" {
-                        continue;
-                    }
-                    let Ok(msg) = serde_json::from_value::<Data>(val) else { continue };
-                    match msg.data.try_into() {
-                        Ok(summary) => {
-                            _ = tx2.send(summary);
-                            connected.take().map(|tx| tx.send(()));
-                        }
-                        Err(_) => {
-                            eprintln!("Invalid bitstamp message format `{json}`");
-                        }
-                    }
+    fn decoder(&self) -> Box<dyn Decoder> {
+        match self.diff_order_book() {
+            true => Box::new(DiffOrderBookDecoder {
+                snapshot_url: format!(
+                    "{}/api/v2/order_book/{}/",
+                    env::var("BITSTAMP_REST_URL")
+                        .unwrap_or_else(|_| "https://www.bitstamp.net".into()),
+                    self.symbol.concat_lower(),
+                ),
+                depth: self.depth,
+                snapshots: SnapshotClient::new(EXCHANGE_NAME),
+                book: <_>::default(),
+                microtimestamp: None,
+            }),
+            false => Box::new(OrderBookDecoder { depth: self.depth }),
+        }
+    }
+}
+
+/// Decodes order book channel messages.
+#[derive(Debug)]
+struct OrderBookDecoder {
+    depth: usize,
+}
+
+#[async_trait::async_trait]
+impl Decoder for OrderBookDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(val) = serde_json::from_str::<serde_json::Value>(json) else {
+            return Ok(None);
+        };
+        if val["event"] != "data" {
+            return Ok(decode_event(&val));
+        }
+        let Ok(mut msg) = serde_json::from_value::<Data>(val) else {
+            return Ok(None);
+        };
+        msg.data.bids.truncate(self.depth);
+        msg.data.asks.truncate(self.depth);
+        let info = ExchangeInfo {
+            event_time: msg.data.time(),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        Summary::parse(EXCHANGE_NAME, &msg.data.bids, &msg.data.asks)
+            .map(|s| Some(Decoded::Summary(s.with_info(info))))
+    }
+}
+
+/// Maintains a local book from diff order book channel messages & REST snapshots.
+///
+/// Diffs at or before the snapshot microtimestamp are discarded.
+#[derive(Debug)]
+struct DiffOrderBookDecoder {
+    snapshot_url: String,
+    depth: usize,
+    snapshots: SnapshotClient,
+    book: LocalBook,
+    /// Microtimestamp of the last applied snapshot or diff, `None` if a new snapshot is required.
+    microtimestamp: Option<u64>,
+}
+
+impl DiffOrderBookDecoder {
+    /// Fetches a REST snapshot replacing the local book.
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        let snapshot: OrderBook = self.snapshots.get(&self.snapshot_url).await?;
+        let microtimestamp = snapshot.microtimestamp.parse()?;
+
+        self.book.clear();
+        let updated = self
+            .book
+            .update_bids(&snapshot.bids)
+            .and_then(|_| self.book.update_asks(&snapshot.asks));
+        anyhow::ensure!(updated.is_ok(), "Invalid snapshot levels");
+
+        self.microtimestamp = Some(microtimestamp);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Decoder for DiffOrderBookDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(val) = serde_json::from_str::<serde_json::Value>(json) else {
+            return Ok(None);
+        };
+        if val["event"] != "data" {
+            return Ok(decode_event(&val));
+        }
+        let Ok(msg) = serde_json::from_value::<Data>(val) else {
+            return Ok(None);
+        };
+        let diff = msg.data;
+        let diff_microtimestamp: u64 = diff.microtimestamp.parse().map_err(|_| ())?;
+
+        let microtimestamp = match self.microtimestamp {
+            Some(mts) => mts,
+            // diffs are buffered by the websocket while fetching the snapshot
+            None if self.snapshots.throttled() => return Ok(None),
+            None => match self.sync().await {
+                Ok(_) => {
+                    self.snapshots.reset();
+                    self.microtimestamp.unwrap_or_default()
+                }
+                Err(err) => {
+                    let retry = self.snapshots.failed();
+                    tracing::warn!(?retry, "snapshot {err}");
+                    return Ok(None);
                 }
-            }
-        });
+            },
+        };
 
-        tokio::time::timeout(Duration::from_secs(12), connected_rx)
-            .await
-            .context("Initial bitstamp connection failed")??;
+        if diff_microtimestamp <= microtimestamp {
+            // already included in the local book
+            return Ok(None);
+        }
 
-        eprintln!("Bitstamp connected");
+        let updated = self
+            .book
+            .update_bids(&diff.bids)
+            .and_then(|_| self.book.update_asks(&diff.asks));
+        if updated.is_err() {
+            self.microtimestamp = None;
+            return Err(());
+        }
+        self.microtimestamp = Some(diff_microtimestamp);
 
-        Ok(Self { tx })
+        let info = ExchangeInfo {
+            event_time: diff.time(),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
+    }
+}
+
+/// Decodes non-data events.
+///
+/// See <https://www.bitstamp.net/websocket/v2/>.
+fn decode_event(val: &serde_json::Value) -> Option<Decoded> {
+    match val["event"].as_str()? {
+        "bts:request_reconnect" => Some(Decoded::Reconnect),
+        "bts:error" => Some(Decoded::SubscriptionError(
+            val["data"]["message"]
+                .as_str()
+                .unwrap_or("unknown error")
+                .to_owned(),
+        )),
+        _ => None,
     }
 }
 
@@ -81,37 +217,18 @@ struct Data {
     pub data: OrderBook,
 }
 
-/// Top 100 bids/asks.
+/// Top 100 bids/asks, full book diff or REST snapshot.
 #[derive(Debug, serde::Deserialize)]
 struct OrderBook {
+    pub microtimestamp: String,
     pub bids: Vec<[String; 2]>,
     pub asks: Vec<[String; 2]>,
 }
 
-impl TryFrom<OrderBook> for merged_order_book_protos::Summary {
-    type Error = ();
-
-    fn try_from(msg: OrderBook) -> Result<Self, Self::Error> {
-        let mut summary = Self {
-            bids: msg
-                .bids
-                .iter()
-                .take(10)
-                .map(|b| (EXCHANGE_NAME, b).try_into())
-                .collect::<Result<_, _>>()?,
-            asks: msg
-                .asks
-                .iter()
-                .take(10)
-                .map(|b| (EXCHANGE_NAME, b).try_into())
-                .collect::<Result<_, _>>()?,
-            ..<_>::default()
-        };
-
-        if !summary.bids.is_empty() && !summary.asks.is_empty() {
-            summary.spread = summary.asks[0].price - summary.bids[0].price;
-        }
-
-        Ok(summary)
+impl OrderBook {
+    /// Event time from the `microtimestamp`.
+    fn time(&self) -> Option<SystemTime> {
+        let micros = self.microtimestamp.parse().ok()?;
+        Some(UNIX_EPOCH + Duration::from_micros(micros))
     }
 }
@@ -0,0 +1,74 @@
+//! Local order book maintenance for incremental exchange streams.
+
+use crate::summary::{Level, Summary};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Full order book built from a snapshot & subsequent updates.
+#[derive(Debug, Default)]
+pub struct LocalBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl LocalBook {
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// Sets or, if the amount is zero, removes bid `[price, amount]` levels.
+    pub fn update_bids(&mut self, levels: &[[String; 2]]) -> Result<(), ()> {
+        update(&mut self.bids, levels)
+    }
+
+    /// Sets or, if the amount is zero, removes ask `[price, amount]` levels.
+    pub fn update_asks(&mut self, levels: &[[String; 2]]) -> Result<(), ()> {
+        update(&mut self.asks, levels)
+    }
+
+    /// Returns `true` if the top bid is at or above the top ask, which a consistent book never is.
+    pub fn is_crossed(&self) -> bool {
+        match (self.bids.last_key_value(), self.asks.first_key_value()) {
+            (Some((bid, _)), Some((ask, _))) => bid >= ask,
+            _ => false,
+        }
+    }
+
+    /// Removes bids/asks beyond the top `depth`.
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_first();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// Returns the top `depth` bids/asks.
+    pub fn summary(&self, exchange: &'static str, depth: usize) -> Summary {
+        let level = |(price, amount): (&Decimal, &Decimal)| Level {
+            exchange,
+            price: *price,
+            amount: *amount,
+        };
+        Summary {
+            bids: self.bids.iter().rev().take(depth).map(level).collect(),
+            asks: self.asks.iter().take(depth).map(level).collect(),
+            ..<_>::default()
+        }
+    }
+}
+
+fn update(side: &mut BTreeMap<Decimal, Decimal>, levels: &[[String; 2]]) -> Result<(), ()> {
+    for [price, amount] in levels {
+        let price: Decimal = price.parse().map_err(|_| ())?;
+        let amount: Decimal = amount.parse().map_err(|_| ())?;
+        if amount.is_zero() {
+            side.remove(&price);
+        } else {
+            side.insert(price, amount);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! bybit exchange.
+
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    summary::ExchangeInfo,
+    symbol::Symbol,
+};
+use std::{
+    env,
+    time::{Duration, UNIX_EPOCH},
+};
+
+const EXCHANGE_NAME: &str = "bybit";
+
+/// Bybit v5 public spot orderbook stream.
+///
+/// Maintains a local book from the snapshot & delta messages.
+#[derive(Debug)]
+pub struct Bybit {
+    pub symbol: Symbol,
+    /// Bids/asks depth, spot orderbook streams provide at most 200.
+    pub depth: usize,
+}
+
+impl Exchange for Bybit {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("BYBIT_URL").unwrap_or_else(|_| "wss://stream.bybit.com/v5/public/spot".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        let topic = format!(
+            "orderbook.{}.{}",
+            orderbook_depth(self.depth),
+            self.symbol.concat_lower().to_ascii_uppercase()
+        );
+        vec![serde_json::json!({"op": "subscribe", "args": [topic]}).to_string()]
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(OrderbookDecoder {
+            depth: self.depth,
+            orderbook_depth: orderbook_depth(self.depth),
+            book: <_>::default(),
+            update_id: None,
+        })
+    }
+
+    /// Bybit requires application level pings to keep connections open.
+    fn ping_message(&self) -> Option<String> {
+        Some(r#"{"op":"ping"}"#.into())
+    }
+}
+
+/// Returns the smallest valid spot orderbook stream depth satisfying `depth`.
+fn orderbook_depth(depth: usize) -> usize {
+    match depth {
+        0..=50 => 50,
+        _ => 200,
+    }
+}
+
+/// Maintains a local book from orderbook stream snapshots & deltas.
+///
+/// See <https://bybit-exchange.github.io/docs/v5/websocket/public/orderbook>.
+#[derive(Debug)]
+struct OrderbookDecoder {
+    depth: usize,
+    /// Subscribed depth, levels beyond this are not updated by bybit.
+    orderbook_depth: usize,
+    book: LocalBook,
+    /// Last applied update id `u`, `None` until a snapshot is applied.
+    update_id: Option<u64>,
+}
+
+#[async_trait::async_trait]
+impl Decoder for OrderbookDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(msg) = serde_json::from_str::<OrderbookMessage>(json) else {
+            return Ok(decode_event(json));
+        };
+        let data = msg.data;
+
+        // `u` of 1 is a snapshot after a bybit service restart, overwriting the book
+        if msg.kind == "snapshot" || data.update_id == 1 {
+            self.book.clear();
+        } else if msg.kind != "delta" {
+            return Ok(None);
+        } else {
+            match self.update_id {
+                // awaiting a snapshot
+                None => return Ok(None),
+                // already applied
+                Some(id) if data.update_id <= id => return Ok(None),
+                Some(_) => {}
+            }
+        }
+
+        let updated = self
+            .book
+            .update_bids(&data.bids)
+            .and_then(|_| self.book.update_asks(&data.asks));
+        if updated.is_err() {
+            self.book.clear();
+            self.update_id = None;
+            return Err(());
+        }
+        // levels pushed beyond the subscribed depth are not removed by deltas
+        self.book.truncate(self.orderbook_depth);
+        self.update_id = Some(data.update_id);
+
+        let info = ExchangeInfo {
+            event_time: Some(UNIX_EPOCH + Duration::from_millis(msg.ts)),
+            sequence: Some(data.update_id),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
+    }
+}
+
+/// Decodes operation responses, a failed `subscribe` is a subscription error.
+fn decode_event(json: &str) -> Option<Decoded> {
+    let response: OpResponse = serde_json::from_str(json).ok()?;
+    match (response.op.as_str(), response.success) {
+        ("ping" | "pong", _) => Some(Decoded::Pong),
+        ("subscribe", Some(false)) => Some(Decoded::SubscriptionError(
+            response.ret_msg.unwrap_or_else(|| "unknown error".into()),
+        )),
+        _ => None,
+    }
+}
+
+/// Operation response, e.g. to `subscribe` or `ping`.
+#[derive(Debug, serde::Deserialize)]
+struct OpResponse {
+    pub op: String,
+    pub success: Option<bool>,
+    pub ret_msg: Option<String>,
+}
+
+/// Orderbook snapshot or delta.
+#[derive(Debug, serde::Deserialize)]
+struct OrderbookMessage {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Event time, unix milliseconds.
+    pub ts: u64,
+    pub data: OrderbookData,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct OrderbookData {
+    #[serde(rename = "b")]
+    pub bids: Vec<[String; 2]>,
+    #[serde(rename = "a")]
+    pub asks: Vec<[String; 2]>,
+    #[serde(rename = "u")]
+    pub update_id: u64,
+}
@@ -0,0 +1,193 @@
+//! coinbase exchange.
+
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    summary::ExchangeInfo,
+    symbol::Symbol,
+};
+use std::env;
+
+const EXCHANGE_NAME: &str = "coinbase";
+
+/// Coinbase Exchange level2 channel.
+///
+/// Maintains a local book from the snapshot & `l2update` messages, resubscribing
+/// for a fresh snapshot when dropped messages are detected.
+#[derive(Debug)]
+pub struct Coinbase {
+    pub symbol: Symbol,
+    pub depth: usize,
+}
+
+impl Coinbase {
+    /// Coinbase product id, e.g. `ETH-BTC`.
+    fn product_id(&self) -> String {
+        format!("{}-{}", self.symbol.base, self.symbol.quote).to_ascii_uppercase()
+    }
+
+    /// Level2 channel subscribe or unsubscribe message.
+    fn channel_message(&self, kind: &str) -> String {
+        let channel = env::var("COINBASE_CHANNEL").unwrap_or_else(|_| "level2_batch".into());
+        serde_json::json!({
+            "type": kind,
+            "product_ids": [self.product_id()],
+            "channels": [channel],
+        })
+        .to_string()
+    }
+}
+
+impl Exchange for Coinbase {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("COINBASE_URL").unwrap_or_else(|_| "wss://ws-feed.exchange.coinbase.com".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![self.channel_message("subscribe")]
+    }
+
+    fn unsubscribe_messages(&self) -> Vec<String> {
+        vec![self.channel_message("unsubscribe")]
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(Level2Decoder {
+            depth: self.depth,
+            book: <_>::default(),
+            synced: false,
+            resubscribing: false,
+        })
+    }
+}
+
+/// Maintains a local book from level2 channel snapshots & updates.
+///
+/// Level2 messages have no sequence numbers, so dropped messages are detected by the
+/// local book becoming crossed, or updates arriving without a snapshot.
+///
+/// See <https://docs.cloud.coinbase.com/exchange/docs/websocket-channels#level2-channel>.
+#[derive(Debug)]
+struct Level2Decoder {
+    depth: usize,
+    book: LocalBook,
+    /// Whether the local book is valid, `false` until a snapshot is applied.
+    synced: bool,
+    /// Whether a resubscribe has been requested & not yet answered with a snapshot.
+    resubscribing: bool,
+}
+
+impl Level2Decoder {
+    /// Discards the local book, requesting a resubscribe unless already requested.
+    fn resubscribe(&mut self) -> Option<Decoded> {
+        self.book.clear();
+        self.synced = false;
+        (!std::mem::replace(&mut self.resubscribing, true)).then_some(Decoded::Resubscribe)
+    }
+}
+
+#[async_trait::async_trait]
+impl Decoder for Level2Decoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let msg = serde_json::from_str::<Message>(json).map_err(|_| ())?;
+
+        let event_time = match msg {
+            Message::Snapshot(snapshot) => {
+                self.book.clear();
+                let updated = self
+                    .book
+                    .update_bids(&snapshot.bids)
+                    .and_then(|_| self.book.update_asks(&snapshot.asks));
+                if updated.is_err() {
+                    self.book.clear();
+                    return Err(());
+                }
+                self.synced = true;
+                self.resubscribing = false;
+                None
+            }
+            Message::L2Update(_) if !self.synced => return Ok(self.resubscribe()),
+            Message::L2Update(update) => {
+                let mut bids = vec![];
+                let mut asks = vec![];
+                for [side, price, size] in update.changes {
+                    match side.as_str() {
+                        "buy" => bids.push([price, size]),
+                        "sell" => asks.push([price, size]),
+                        _ => return Err(()),
+                    }
+                }
+                let updated = self
+                    .book
+                    .update_bids(&bids)
+                    .and_then(|_| self.book.update_asks(&asks));
+                if updated.is_err() {
+                    self.book.clear();
+                    self.synced = false;
+                    return Err(());
+                }
+                if self.book.is_crossed() {
+                    tracing::warn!("crossed book, resubscribing");
+                    return Ok(self.resubscribe());
+                }
+                update.time.and_then(|t| humantime::parse_rfc3339(&t).ok())
+            }
+            Message::Error(err) => {
+                let reason = err.reason.map(|r| format!(": {r}")).unwrap_or_default();
+                return Ok(Some(Decoded::SubscriptionError(err.message + &reason)));
+            }
+            Message::Other => return Ok(None),
+        };
+
+        let info = ExchangeInfo {
+            event_time,
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
+    }
+}
+
+/// Websocket feed message.
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type")]
+enum Message {
+    #[serde(rename = "snapshot")]
+    Snapshot(Snapshot),
+    #[serde(rename = "l2update")]
+    L2Update(L2Update),
+    #[serde(rename = "error")]
+    Error(ErrorMessage),
+    /// E.g. `subscriptions` & `heartbeat`.
+    #[serde(other)]
+    Other,
+}
+
+/// Full level2 book.
+#[derive(Debug, serde::Deserialize)]
+struct Snapshot {
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// Level2 `[side, price, size]` changes, a zero size removes the level.
+#[derive(Debug, serde::Deserialize)]
+struct L2Update {
+    pub changes: Vec<[String; 3]>,
+    /// Update time, RFC3339.
+    pub time: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ErrorMessage {
+    pub message: String,
+    pub reason: Option<String>,
+}
@@ -0,0 +1,509 @@
+//! Common exchange websocket handling.
+
+use crate::{
+    backoff::Backoff,
+    metrics::{LastMessage, METRICS},
+    summary::Summary,
+    symbol::Symbol,
+};
+use anyhow::Context;
+use futures_util::{SinkExt, StreamExt};
+use std::{
+    env,
+    future::Future,
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::Instrument;
+
+/// Minimum interval between invalid message warnings of a connection.
+const INVALID_MESSAGE_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An exchange order book websocket stream.
+pub trait Exchange: Send + Sync + 'static {
+    /// Exchange name, used as the [`Level::exchange`](crate::summary::Level) value.
+    fn name(&self) -> &'static str;
+
+    /// Order book trading pair.
+    fn symbol(&self) -> &Symbol;
+
+    /// Websocket url to connect to.
+    fn url(&self) -> String;
+
+    /// Messages to send after connecting to subscribe to the order book stream.
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Messages to send to unsubscribe from the order book stream, before resubscribing.
+    fn unsubscribe_messages(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns a new message decoder for a connection.
+    fn decoder(&self) -> Box<dyn Decoder>;
+
+    /// Application level ping message, sent instead of websocket pings & answered with [`Decoded::Pong`].
+    fn ping_message(&self) -> Option<String> {
+        None
+    }
+
+    /// Default interval to replace connections, e.g. before an exchange connection time limit.
+    fn rotate_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Per-connection websocket message decoder.
+#[async_trait::async_trait]
+pub trait Decoder: Send {
+    /// Decodes a websocket text message.
+    ///
+    /// Returns `Ok(None)` for messages that do not require handling,
+    /// or `Err` for order book messages with an invalid format.
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()>;
+}
+
+/// A decoded websocket message requiring handling.
+#[derive(Debug)]
+pub enum Decoded {
+    /// New order book summary.
+    Summary(Summary),
+    /// The exchange requested a re-connect, e.g. before maintenance.
+    Reconnect,
+    /// The exchange rejected the subscription.
+    SubscriptionError(String),
+    /// The local book is invalid, e.g. failed a checksum, & requires a fresh subscription snapshot.
+    ///
+    /// Handled by the connection.
+    Resubscribe,
+    /// Application level ping response, see [`Exchange::ping_message`].
+    ///
+    /// Handled by the connection.
+    Pong,
+}
+
+/// Exchange summary broadcaster.
+#[derive(Debug)]
+pub struct ExchangeClient {
+    pub name: &'static str,
+    pub tx: broadcast::Sender<Summary>,
+    /// Time without updates after which the exchange levels are considered stale.
+    pub stale_timeout: Option<Duration>,
+    /// Receive time of the latest update with an observed merge latency,
+    /// shared by mergers so each update is observed once.
+    pub merge_observed: Arc<Mutex<Option<SystemTime>>>,
+    /// Connection task, stopped when the client is dropped.
+    task: tokio::task::JoinHandle<()>,
+    symbol: String,
+    /// Last message time shared with connections, its metric is removed when the client is dropped.
+    last_message: LastMessage,
+}
+
+impl Drop for ExchangeClient {
+    fn drop(&mut self) {
+        self.task.abort();
+        METRICS.remove_last_message(self.name, &self.symbol, &self.last_message);
+    }
+}
+
+impl ExchangeClient {
+    /// Connects to the exchange order book stream, re-connecting on close.
+    ///
+    /// Also returns a future that resolves once the first summary has been received,
+    /// or fails if that takes too long.
+    pub fn start(
+        exchange: Box<dyn Exchange>,
+    ) -> (Self, impl Future<Output = anyhow::Result<()>> + Send) {
+        let name = exchange.name();
+        let symbol = exchange.symbol().to_string();
+        let last_message = METRICS.last_message(name, &symbol);
+        let (tx, _) = broadcast::channel(1);
+        let (connected_tx, connected_rx) = oneshot::channel();
+
+        let span = tracing::info_span!("exchange", exchange = name, %symbol);
+        let task = tokio::spawn(
+            run(
+                exchange.into(),
+                tx.clone(),
+                connected_tx,
+                Arc::clone(&last_message),
+            )
+            .instrument(span),
+        );
+
+        let connected = async move {
+            tokio::time::timeout(Duration::from_secs(12), connected_rx)
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .with_context(|| format!("Initial {name} connection failed"))?
+                .map_err(|err| anyhow::anyhow!("{name} subscription failed: {err}"))?;
+            tracing::info!(exchange = name, "connected");
+            Ok(())
+        };
+
+        let client = Self {
+            name,
+            tx,
+            stale_timeout: stale_timeout(name),
+            merge_observed: <_>::default(),
+            task,
+            symbol,
+            last_message,
+        };
+        (client, connected)
+    }
+}
+
+/// Reads `{NAME}_STALE_TIMEOUT_MS`, falling back to `STALE_TIMEOUT_MS`. Default 30s, zero disables.
+fn stale_timeout(name: &str) -> Option<Duration> {
+    env_millis(name, "STALE_TIMEOUT_MS", 30_000)
+}
+
+/// Reads re-connect `{NAME}_RECONNECT_*` or `RECONNECT_*` config.
+pub fn reconnect_backoff(name: &str) -> Backoff {
+    Backoff::new(
+        env_millis(name, "RECONNECT_DELAY_MS", 500).unwrap_or_default(),
+        env_millis(name, "RECONNECT_MAX_DELAY_MS", 30_000).unwrap_or_default(),
+        Some(env_parse(name, "RECONNECT_MAX_ATTEMPTS").unwrap_or(0)).filter(|max| *max > 0),
+    )
+}
+
+/// Reads `{NAME}_{var}` milliseconds, falling back to `var` then `default`. Zero returns `None`.
+pub fn env_millis(name: &str, var: &str, default: u64) -> Option<Duration> {
+    let ms = env_parse(name, var).unwrap_or(default);
+    Some(Duration::from_millis(ms)).filter(|t| !t.is_zero())
+}
+
+/// Parses `{NAME}_{var}`, falling back to `var`.
+fn env_parse<T: std::str::FromStr>(name: &str, var: &str) -> Option<T> {
+    env::var(format!("{}_{var}", name.to_ascii_uppercase()))
+        .or_else(|_| env::var(var))
+        .ok()
+        .and_then(|v| v.parse().ok())
+}
+
+/// Connection loop, decodes & broadcasts summaries forever.
+///
+/// Re-connect requests open a new connection, switching over once it provides
+/// a summary so there is no gap in updates.
+async fn run(
+    exchange: Arc<dyn Exchange>,
+    tx: broadcast::Sender<Summary>,
+    connected_tx: oneshot::Sender<Result<(), String>>,
+    last_message: LastMessage,
+) {
+    let name = exchange.name();
+    let reconnects = METRICS
+        .exchange_reconnects
+        .with_label_values(&[name, &exchange.symbol().to_string()]);
+    let rotate_interval = env_millis(
+        name,
+        "ROTATE_INTERVAL_MS",
+        exchange.rotate_interval().map_or(0, |t| t.as_millis() as _),
+    );
+    let mut backoff = reconnect_backoff(name);
+    let mut connected = Some(connected_tx);
+    let mut first_attempt = true;
+    'connect: loop {
+        if !std::mem::take(&mut first_attempt) {
+            let Some(delay) = backoff.next_delay() else {
+                tracing::error!(attempts = backoff.attempts(), "giving up re-connecting");
+                return;
+            };
+            tokio::time::sleep(delay).await;
+            reconnects.inc();
+        }
+
+        let mut current = spawn_connection(&exchange, &last_message);
+        let mut replacement = None;
+        let mut rotate_at = rotate_interval.map(|t| Instant::now() + t);
+        let mut rotate_backoff = reconnect_backoff(name);
+
+        loop {
+            // rotate unless already replacing
+            let rotate_deadline = rotate_at.filter(|_| replacement.is_none());
+            tokio::select! {
+                event = current.recv() => match event {
+                    Some(Decoded::Summary(summary)) => {
+                        _ = tx.send(summary);
+                        connected.take().map(|tx| tx.send(Ok(())));
+                        backoff.reset();
+                    }
+                    Some(Decoded::Reconnect) => {
+                        tracing::info!("re-connect requested");
+                        if replacement.is_none() {
+                            reconnects.inc();
+                            replacement = Some(spawn_connection(&exchange, &last_message));
+                        }
+                    }
+                    Some(Decoded::Resubscribe | Decoded::Pong) => {}
+                    Some(Decoded::SubscriptionError(err)) => {
+                        tracing::error!("subscription error: {err}");
+                        // keep trying, if startup requires this exchange it drops the client
+                        if let Some(connected) = connected.take() {
+                            _ = connected.send(Err(err));
+                        }
+                        continue 'connect;
+                    }
+                    None => match replacement.take() {
+                        // closed before the replacement is ready, keep waiting for it
+                        Some(rx) => {
+                            current = rx;
+                            rotate_at = rotate_interval.map(|t| Instant::now() + t);
+                        }
+                        None => continue 'connect,
+                    },
+                },
+                event = recv_replacement(&mut replacement) => match event {
+                    Some(Decoded::Summary(summary)) => {
+                        tracing::info!("switched to new connection");
+                        current = replacement.take().expect("replacement");
+                        rotate_at = rotate_interval.map(|t| Instant::now() + t);
+                        rotate_backoff.reset();
+                        _ = tx.send(summary);
+                    }
+                    Some(Decoded::Reconnect | Decoded::Resubscribe | Decoded::Pong) => {}
+                    Some(Decoded::SubscriptionError(err)) => {
+                        tracing::error!("new connection subscription error: {err}");
+                        replacement = None;
+                        rotate_at = rotate_backoff.next_delay().map(|d| Instant::now() + d);
+                    }
+                    None => {
+                        replacement = None;
+                        rotate_at = rotate_backoff.next_delay().map(|d| Instant::now() + d);
+                    }
+                },
+                _ = deadline(rotate_deadline) => {
+                    tracing::info!("rotating connection");
+                    reconnects.inc();
+                    replacement = Some(spawn_connection(&exchange, &last_message));
+                },
+            }
+        }
+    }
+}
+
+/// Spawns a new exchange connection task returning its decoded messages.
+///
+/// The connection is closed when the receiver is dropped.
+fn spawn_connection(
+    exchange: &Arc<dyn Exchange>,
+    last_message: &LastMessage,
+) -> mpsc::Receiver<Decoded> {
+    static CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+    let (events_tx, events_rx) = mpsc::channel(16);
+    let id = CONNECTION_ID.fetch_add(1, atomic::Ordering::Relaxed);
+    tokio::spawn(
+        connect(Arc::clone(exchange), events_tx, Arc::clone(last_message))
+            .instrument(tracing::info_span!("connection", id)),
+    );
+    events_rx
+}
+
+/// Receives from the replacement connection, or never if there isn't one.
+async fn recv_replacement(replacement: &mut Option<mpsc::Receiver<Decoded>>) -> Option<Decoded> {
+    match replacement {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Connects, subscribes & sends decoded messages until the connection fails or is no longer used.
+async fn connect(
+    exchange: Arc<dyn Exchange>,
+    events: mpsc::Sender<Decoded>,
+    last_message: LastMessage,
+) {
+    let name = exchange.name();
+    let url = exchange.url();
+    let ping_interval = env_millis(name, "PING_INTERVAL_MS", 10_000);
+    let no_data_timeout = env_millis(name, "NO_DATA_TIMEOUT_MS", 30_000);
+    let connect_timeout = env_millis(name, "CONNECT_TIMEOUT_MS", 10_000);
+
+    // a blackholed connect or stalled handshake would otherwise never time out
+    let connected = async {
+        let (stream, _) = tokio_tungstenite::connect_async(&url)
+            .await
+            .with_context(|| url.clone())?;
+        let (mut ws_write, ws_read) = stream.split();
+        for sub_msg in exchange.subscribe_messages() {
+            ws_write
+                .send(Message::Text(sub_msg))
+                .await
+                .context("subscribe")?;
+        }
+        anyhow::Ok((ws_write, ws_read))
+    };
+    let connected = match connect_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, connected)
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("{url}: connect timed out"))),
+        None => connected.await,
+    };
+    let (mut ws_write, mut ws_read) = match connected {
+        Ok(ws) => ws,
+        Err(err) => {
+            tracing::warn!("{err:#}");
+            return;
+        }
+    };
+
+    let mut decoder = exchange.decoder();
+
+    let symbol = exchange.symbol().to_string();
+    let messages = METRICS
+        .exchange_messages
+        .with_label_values(&[name, &symbol]);
+    let invalid_messages = METRICS
+        .exchange_invalid_messages
+        .with_label_values(&[name, &symbol]);
+    let exchange_latency = METRICS.exchange_latency.with_label_values(&[name, &symbol]);
+    let mut invalid_log = LogLimiter::new(INVALID_MESSAGE_LOG_INTERVAL);
+
+    let mut ping =
+        ping_interval.map(|period| tokio::time::interval_at(Instant::now() + period, period));
+    let ping_message = exchange.ping_message();
+    let mut awaiting_pong = false;
+    let mut last_data = Instant::now();
+
+    loop {
+        let msg = tokio::select! {
+            msg = ws_read.next() => msg,
+            _ = tick(&mut ping) => {
+                if awaiting_pong {
+                    tracing::warn!("ping timeout, reconnecting");
+                    return;
+                }
+                let ping = match &ping_message {
+                    Some(msg) => Message::Text(msg.clone()),
+                    None => Message::Ping(vec![]),
+                };
+                if let Err(err) = ws_write.send(ping).await {
+                    tracing::warn!("ping {err}");
+                    return;
+                }
+                awaiting_pong = true;
+                continue;
+            }
+            _ = deadline(no_data_timeout.map(|t| last_data + t)) => {
+                tracing::warn!("no data received, reconnecting");
+                return;
+            }
+            _ = events.closed() => {
+                _ = ws_write.close().await;
+                return;
+            }
+        };
+        let json = match msg {
+            None => return, // connection closed
+            Some(Ok(Message::Text(json))) => json,
+            Some(Ok(Message::Pong(_))) => {
+                awaiting_pong = false;
+                continue;
+            }
+            Some(_) => continue,
+        };
+        let prev_data = std::mem::replace(&mut last_data, Instant::now());
+        messages.inc();
+        *last_message.lock().unwrap() = last_data.into_std();
+        let received = SystemTime::now();
+        match decoder.decode(&json).await {
+            Ok(Some(Decoded::Pong)) => {
+                awaiting_pong = false;
+                // pongs are not data, so should not hold off the no data watchdog
+                last_data = prev_data;
+            }
+            Ok(Some(Decoded::Resubscribe)) => {
+                let messages = exchange
+                    .unsubscribe_messages()
+                    .into_iter()
+                    .chain(exchange.subscribe_messages());
+                for msg in messages {
+                    if let Err(err) = ws_write.send(Message::Text(msg)).await {
+                        tracing::warn!("resubscribe {err}");
+                        return;
+                    }
+                }
+            }
+            Ok(Some(mut decoded)) => {
+                if let Decoded::Summary(summary) = &mut decoded {
+                    for info in &mut summary.exchanges {
+                        info.receive_time = Some(received);
+                        if let Some(latency) = info
+                            .event_time
+                            .and_then(|event| received.duration_since(event).ok())
+                        {
+                            exchange_latency.observe(latency.as_secs_f64());
+                        }
+                    }
+                }
+                let failed = matches!(decoded, Decoded::SubscriptionError(_));
+                if events.send(decoded).await.is_err() || failed {
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(_) => {
+                invalid_messages.inc();
+                if let Some(suppressed) = invalid_log.allow() {
+                    tracing::warn!(suppressed, "invalid message format `{json}`");
+                }
+            }
+        }
+    }
+}
+
+/// Limits logging to once per interval.
+#[derive(Debug)]
+struct LogLimiter {
+    interval: Duration,
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl LogLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of suppressed logs since the last allowed, or `None` if this log should be suppressed.
+    fn allow(&mut self) -> Option<u64> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last = Some(Instant::now());
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+/// Ticks the interval, or never if `None`.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => _ = interval.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleeps until the deadline, or forever if `None`.
+async fn deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,68 @@
+//! Price tick grouping of merged levels.
+
+use crate::summary::{AggregatedLevel, Level, Summary};
+use rust_decimal::Decimal;
+
+/// Smallest supported `Tick::Size`, finer than any exchange's price increments.
+///
+/// Keeps `price / size` within `Decimal` range for prices up to ~7.9e16.
+pub const MIN_TICK_SIZE: Decimal = Decimal::from_parts(1, 0, 0, false, 12);
+
+/// Price bucket size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tick {
+    /// Fixed price bucket size, e.g. `0.00001`.
+    Size(Decimal),
+    /// Bucket size in basis points of the mid price.
+    ///
+    /// Rounded down to one significant figure so buckets are stable as the price moves.
+    Bps(u32),
+}
+
+impl Tick {
+    /// Returns the bucket size for the sorted summary, `None` if it cannot be determined.
+    pub fn size(self, summary: &Summary) -> Option<Decimal> {
+        match self {
+            Self::Size(size) => Some(size),
+            Self::Bps(bps) => {
+                let mid =
+                    (summary.bids.first()?.price + summary.asks.first()?.price) / Decimal::TWO;
+                let size = mid.checked_mul(Decimal::from(bps))? / Decimal::from(10_000);
+                round_to_significant_figure(size)
+            }
+        }
+    }
+}
+
+/// Rounds down a positive value to one significant figure, e.g. `0.0000714 -> 0.00007`.
+fn round_to_significant_figure(value: Decimal) -> Option<Decimal> {
+    if value <= Decimal::ZERO {
+        return None;
+    }
+    let mut unit = Decimal::ONE;
+    while unit > value {
+        unit /= Decimal::TEN;
+    }
+    while unit * Decimal::TEN <= value {
+        unit *= Decimal::TEN;
+    }
+    Some((value / unit).floor() * unit)
+}
+
+/// Groups sorted bids into buckets of `size`, rounding prices down.
+pub fn group_bids(bids: &[Level], size: Decimal) -> Vec<AggregatedLevel> {
+    AggregatedLevel::group(bids, |price| bucket(price, size, Decimal::floor))
+}
+
+/// Groups sorted asks into buckets of `size`, rounding prices up.
+pub fn group_asks(asks: &[Level], size: Decimal) -> Vec<AggregatedLevel> {
+    AggregatedLevel::group(asks, |price| bucket(price, size, Decimal::ceil))
+}
+
+/// Returns the `round`ed bucket of `price`, or `price` itself if out of `Decimal` range.
+fn bucket(price: Decimal, size: Decimal, round: fn(&Decimal) -> Decimal) -> Decimal {
+    price
+        .checked_div(size)
+        .and_then(|buckets| round(&buckets).checked_mul(size))
+        .unwrap_or(price)
+}
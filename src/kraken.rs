@@ -0,0 +1,321 @@
+//! kraken exchange.
+
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    summary::{ExchangeInfo, Summary},
+    symbol::Symbol,
+};
+use serde_json::value::RawValue;
+use std::env;
+
+const EXCHANGE_NAME: &str = "kraken";
+
+/// Levels included in the book checksum.
+const CHECKSUM_DEPTH: usize = 10;
+
+/// Kraken websocket v2 book channel.
+///
+/// Maintains a local book from the snapshot & updates, verifying the CRC32 checksum
+/// after every message & resubscribing on mismatch. The `instrument` channel is also
+/// subscribed to for the pair's checksum price & qty precisions.
+#[derive(Debug)]
+pub struct Kraken {
+    pub symbol: Symbol,
+    /// Bids/asks depth, the book channel provides at most 1000.
+    pub depth: usize,
+}
+
+impl Kraken {
+    /// Kraken v2 pair, e.g. `ETH/BTC`.
+    fn pair(&self) -> String {
+        self.symbol.to_string().to_ascii_uppercase()
+    }
+
+    /// Instrument channel subscribe or unsubscribe message.
+    fn instrument_message(method: &str) -> String {
+        serde_json::json!({
+            "method": method,
+            "params": {"channel": "instrument"},
+        })
+        .to_string()
+    }
+
+    /// Book channel subscribe or unsubscribe message.
+    fn book_message(&self, method: &str) -> String {
+        serde_json::json!({
+            "method": method,
+            "params": {
+                "channel": "book",
+                "symbol": [self.pair()],
+                "depth": book_depth(self.depth),
+            },
+        })
+        .to_string()
+    }
+}
+
+impl Exchange for Kraken {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("KRAKEN_URL").unwrap_or_else(|_| "wss://ws.kraken.com/v2".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        // precisions should be received before the book snapshot
+        vec![
+            Self::instrument_message("subscribe"),
+            self.book_message("subscribe"),
+        ]
+    }
+
+    fn unsubscribe_messages(&self) -> Vec<String> {
+        vec![
+            self.book_message("unsubscribe"),
+            Self::instrument_message("unsubscribe"),
+        ]
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(BookDecoder {
+            pair: self.pair(),
+            precision: None,
+            depth: self.depth,
+            book_depth: book_depth(self.depth),
+            book: <_>::default(),
+            synced: false,
+            resubscribing: false,
+        })
+    }
+}
+
+/// Returns the smallest valid book channel depth satisfying `depth`.
+fn book_depth(depth: usize) -> usize {
+    match depth {
+        0..=10 => 10,
+        11..=25 => 25,
+        26..=100 => 100,
+        101..=500 => 500,
+        _ => 1000,
+    }
+}
+
+/// Maintains a local book from book channel snapshots & updates.
+///
+/// See <https://docs.kraken.com/api/docs/guides/spot-ws-book-v2>.
+#[derive(Debug)]
+struct BookDecoder {
+    /// Kraken v2 pair, e.g. `ETH/BTC`.
+    pair: String,
+    /// Checksum precisions from the instrument channel.
+    precision: Option<Precision>,
+    depth: usize,
+    /// Subscribed depth, levels beyond this are not updated by kraken.
+    book_depth: usize,
+    book: LocalBook,
+    /// Whether the local book is valid, `false` until a snapshot is applied.
+    synced: bool,
+    /// Whether a resubscribe has been requested & not yet answered with a snapshot.
+    resubscribing: bool,
+}
+
+impl BookDecoder {
+    /// Discards the local book, requesting a resubscribe unless already requested.
+    fn resubscribe(&mut self) -> Option<Decoded> {
+        self.book.clear();
+        self.synced = false;
+        (!std::mem::replace(&mut self.resubscribing, true)).then_some(Decoded::Resubscribe)
+    }
+
+    /// Decodes non-book messages, updating the pair's precisions from instrument messages.
+    fn decode_other(&mut self, json: &str) -> Option<Decoded> {
+        let Ok(msg) = serde_json::from_str::<InstrumentMessage>(json) else {
+            return decode_event(json);
+        };
+        if msg.channel == "instrument" {
+            if let Some(pair) = msg.data.pairs.into_iter().find(|p| p.symbol == self.pair) {
+                self.precision = Some(Precision {
+                    price: pair.price_precision,
+                    qty: pair.qty_precision,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Pair decimal precisions, used to format checksum values.
+#[derive(Debug, Clone, Copy)]
+struct Precision {
+    price: u32,
+    qty: u32,
+}
+
+#[async_trait::async_trait]
+impl Decoder for BookDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let msg = match serde_json::from_str::<BookMessage>(json) {
+            Ok(msg) => msg,
+            Err(_) => return Ok(self.decode_other(json)),
+        };
+        if msg.channel != "book" {
+            return Ok(None);
+        }
+        let Some(data) = msg.data.first() else {
+            return Err(());
+        };
+
+        match msg.kind.as_str() {
+            "snapshot" => {
+                self.book.clear();
+                self.synced = true;
+                self.resubscribing = false;
+            }
+            "update" if self.synced => {}
+            // updates after a discarded book, until the resubscribe snapshot
+            "update" => return Ok(self.resubscribe()),
+            _ => return Ok(None),
+        }
+
+        let updated = self
+            .book
+            .update_bids(&levels(&data.bids))
+            .and_then(|_| self.book.update_asks(&levels(&data.asks)));
+        if updated.is_err() {
+            self.book.clear();
+            self.synced = false;
+            return Err(());
+        }
+        // levels pushed beyond the subscribed depth are not removed by updates
+        self.book.truncate(self.book_depth);
+
+        let checksum = checksum(
+            &self.book.summary(EXCHANGE_NAME, CHECKSUM_DEPTH),
+            self.precision,
+        );
+        if checksum != data.checksum {
+            tracing::warn!(
+                expected = data.checksum,
+                checksum,
+                "book checksum mismatch, resubscribing"
+            );
+            return Ok(self.resubscribe());
+        }
+
+        let info = ExchangeInfo {
+            event_time: data
+                .timestamp
+                .as_deref()
+                .and_then(|t| humantime::parse_rfc3339(t).ok()),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
+    }
+}
+
+/// Returns `[price, qty]` levels preserving the exact received decimals.
+fn levels(levels: &[Level<'_>]) -> Vec<[String; 2]> {
+    levels
+        .iter()
+        .map(|l| [l.price.get().to_owned(), l.qty.get().to_owned()])
+        .collect()
+}
+
+/// CRC32 of the top 10 asks then top 10 bids, each price & qty formatted to the pair's
+/// precision & concatenated without the decimal point & leading zeros.
+///
+/// Kraken sends json floats which may omit trailing zeros, so without precisions
+/// (not yet received) the received decimals are used as is.
+fn checksum(book: &Summary, precision: Option<Precision>) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for level in book.asks.iter().chain(&book.bids) {
+        let (mut price, mut amount) = (level.price, level.amount);
+        if let Some(precision) = precision {
+            price.rescale(precision.price);
+            amount.rescale(precision.qty);
+        }
+        for value in [price, amount] {
+            let digits = value.to_string().replace('.', "");
+            hasher.update(digits.trim_start_matches('0').as_bytes());
+        }
+    }
+    hasher.finalize()
+}
+
+/// Decodes non-book messages, a failed `subscribe` response is a subscription error.
+fn decode_event(json: &str) -> Option<Decoded> {
+    let response: MethodResponse = serde_json::from_str(json).ok()?;
+    match (response.method.as_str(), response.success) {
+        ("subscribe", false) => Some(Decoded::SubscriptionError(
+            response.error.unwrap_or_else(|| "unknown error".into()),
+        )),
+        _ => None,
+    }
+}
+
+/// Method response, e.g. to `subscribe`.
+#[derive(Debug, serde::Deserialize)]
+struct MethodResponse {
+    pub method: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Instrument channel snapshot or update.
+#[derive(Debug, serde::Deserialize)]
+struct InstrumentMessage {
+    pub channel: String,
+    pub data: InstrumentData,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct InstrumentData {
+    #[serde(default)]
+    pub pairs: Vec<PairInfo>,
+}
+
+/// Trading pair reference data, other fields are ignored.
+#[derive(Debug, serde::Deserialize)]
+struct PairInfo {
+    pub symbol: String,
+    pub price_precision: u32,
+    pub qty_precision: u32,
+}
+
+/// Book channel snapshot or update.
+#[derive(Debug, serde::Deserialize)]
+struct BookMessage<'a> {
+    pub channel: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(borrow)]
+    pub data: Vec<BookData<'a>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BookData<'a> {
+    #[serde(borrow)]
+    pub bids: Vec<Level<'a>>,
+    #[serde(borrow)]
+    pub asks: Vec<Level<'a>>,
+    pub checksum: u32,
+    /// Update time, RFC3339. Not provided by snapshots.
+    pub timestamp: Option<String>,
+}
+
+/// Price level, numbers are kept raw to avoid float rounding.
+#[derive(Debug, serde::Deserialize)]
+struct Level<'a> {
+    #[serde(borrow)]
+    pub price: &'a RawValue,
+    #[serde(borrow)]
+    pub qty: &'a RawValue,
+}
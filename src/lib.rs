@@ -1,32 +1,146 @@
+mod backoff;
 mod binance;
+mod bitfinex;
 mod bitstamp;
+mod book;
+mod bybit;
+mod coinbase;
+mod exchange;
+mod grouping;
+mod kraken;
 mod merger;
+mod metrics;
+mod okx;
+mod snapshot;
+mod startup;
+mod summary;
+mod symbol;
 
-use crate::{binance::BinanceClient, bitstamp::BitstampClient, merger::Top10SummaryMerger};
+use crate::{
+    binance::Binance,
+    bitfinex::Bitfinex,
+    bitstamp::Bitstamp,
+    bybit::Bybit,
+    coinbase::Coinbase,
+    exchange::{Exchange, ExchangeClient},
+    grouping::Tick,
+    kraken::Kraken,
+    merger::{MergeOptions, SummaryMerger},
+    metrics::METRICS,
+    okx::Okx,
+    startup::StartupPolicy,
+    symbol::Symbol,
+};
 use futures_util::{Stream, StreamExt};
 use merged_order_book_protos::orderbook_aggregator_server::OrderbookAggregatorServer;
-use std::{env, pin::Pin};
-use tokio_stream::wrappers::BroadcastStream;
+use std::{
+    collections::HashMap,
+    env,
+    pin::Pin,
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use tonic::Status;
 
-/// Starts the grpc server & connects to binance & bitstamp ethbtc exchanges.
+/// Starts the grpc server & connects to the `EXCHANGES` for each configured symbol.
+///
+/// If `LAZY_SUBSCRIPTIONS` is set exchanges are instead connected on a symbol's first request.
 pub async fn start() -> anyhow::Result<()> {
-    // connect to exchanges & await first message concurrently
-    let (binance, bitstamp) = futures_util::try_join!(
-        BinanceClient::start("ethbtc"),
-        BitstampClient::start("ethbtc"),
-    )?;
-    let merger =
-        Top10SummaryMerger::listen_to(vec![binance.tx.subscribe(), bitstamp.tx.subscribe()]);
+    let symbols: Vec<Symbol> = env::var("SYMBOLS")
+        .unwrap_or_else(|_| "eth/btc".into())
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    anyhow::ensure!(!symbols.is_empty(), "No SYMBOLS configured");
 
-    let service = OrderbookAggregatorServer::new(GrcServer { events: merger });
+    let max_depth: usize = env::var("BOOK_DEPTH")
+        .ok()
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(10);
+    anyhow::ensure!(max_depth > 0, "BOOK_DEPTH must be positive");
+
+    let supported: Vec<_> = all_exchanges(&symbols[0], max_depth)
+        .iter()
+        .map(|ex| ex.name())
+        .collect();
+    let mut exchange_names = vec![];
+    for name in env::var("EXCHANGES")
+        .unwrap_or_else(|_| "binance,bitstamp".into())
+        .split(',')
+    {
+        let name = name.trim().to_ascii_lowercase();
+        let name = supported.iter().find(|s| **s == name).ok_or_else(|| {
+            anyhow::anyhow!("Unknown exchange `{name}`, expected one of {supported:?}")
+        })?;
+        if !exchange_names.contains(name) {
+            exchange_names.push(*name);
+        }
+    }
+
+    let startup_policy: StartupPolicy = env::var("STARTUP_POLICY")
+        .ok()
+        .map(|p| p.parse())
+        .transpose()?
+        .unwrap_or_default();
+
+    let lazy_grace = env::var("LAZY_SUBSCRIPTIONS")
+        .ok()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(false)
+        .then(|| {
+            let ms = env::var("SUBSCRIPTION_GRACE_MS")
+                .ok()
+                .and_then(|g| g.parse().ok())
+                .unwrap_or(60_000);
+            Duration::from_millis(ms)
+        });
+
+    let mut state = State::default();
+    if lazy_grace.is_none() {
+        // connect to exchanges & await first messages concurrently
+        let exchange_names = &exchange_names;
+        let clients = futures_util::future::try_join_all(symbols.iter().map(|symbol| async move {
+            let (clients, connected): (Vec<_>, Vec<_>) =
+                exchanges(symbol, max_depth, exchange_names)
+                    .into_iter()
+                    .map(ExchangeClient::start)
+                    .unzip();
+            startup_policy.await_connected(connected).await?;
+            anyhow::Ok((symbol.clone(), SymbolClients::new(clients)))
+        }))
+        .await?;
+        state.clients = clients.into_iter().collect();
+    }
+
+    let service = OrderbookAggregatorServer::new(GrcServer {
+        default_symbol: symbols[0].clone(),
+        exchange_names,
+        symbols,
+        max_depth,
+        lazy_grace,
+        state: Arc::new(Mutex::new(state)),
+    });
 
     let port: u16 = env::var("GRPC_PORT")
         .ok()
         .and_then(|p| p.parse().ok())
         .unwrap_or(7016);
 
-    eprintln!("Starting grpc server on {port}");
+    let metrics_port: u16 = env::var("METRICS_PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(7017);
+    if metrics_port != 0 {
+        let metrics = metrics::serve(metrics_port)?;
+        tracing::info!("Starting metrics server on {metrics_port}");
+        tokio::spawn(metrics);
+    }
+
+    tracing::info!("Starting grpc server on {port}");
 
     tonic::transport::Server::builder()
         .add_service(service)
@@ -36,9 +150,311 @@ pub async fn start() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Returns the named exchanges to merge for a symbol.
+fn exchanges(symbol: &Symbol, depth: usize, names: &[&str]) -> Vec<Box<dyn Exchange>> {
+    all_exchanges(symbol, depth)
+        .into_iter()
+        .filter(|ex| names.contains(&ex.name()))
+        .collect()
+}
+
+/// Returns all supported exchanges for a symbol.
+fn all_exchanges(symbol: &Symbol, depth: usize) -> Vec<Box<dyn Exchange>> {
+    vec![
+        Box::new(Binance {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Bitstamp {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Kraken {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Coinbase {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Okx {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Bybit {
+            symbol: symbol.clone(),
+            depth,
+        }),
+        Box::new(Bitfinex {
+            symbol: symbol.clone(),
+            depth,
+        }),
+    ]
+}
+
 #[derive(Debug)]
 pub struct GrcServer {
-    events: Top10SummaryMerger,
+    /// Symbol used by requests that don't specify one.
+    default_symbol: Symbol,
+    /// Supported symbols.
+    symbols: Vec<Symbol>,
+    /// Names of the exchanges of each symbol.
+    exchange_names: Vec<&'static str>,
+    /// Maximum & default merged bids/asks depth.
+    max_depth: usize,
+    /// Time to keep exchange subscriptions without requests, `None` if always subscribed.
+    lazy_grace: Option<Duration>,
+    state: Arc<Mutex<State>>,
+}
+
+/// Exchange subscriptions & mergers.
+#[derive(Debug, Default)]
+struct State {
+    clients: HashMap<Symbol, SymbolClients>,
+    /// Mergers of current requests, shared by requests with the same parameters.
+    mergers: HashMap<MergerKey, SharedMerger>,
+}
+
+/// Merger shared by requests with the same parameters.
+#[derive(Debug)]
+struct SharedMerger {
+    merger: SummaryMerger,
+    /// Number of current requests, the merger is removed when the last ends.
+    subscribers: usize,
+}
+
+/// Exchange clients of a symbol.
+#[derive(Debug)]
+struct SymbolClients {
+    exchanges: Vec<ExchangeClient>,
+    /// Number of current requests.
+    subscribers: usize,
+    /// When the last request ended, if there are no current requests.
+    unused_since: Option<Instant>,
+}
+
+impl SymbolClients {
+    fn new(exchanges: Vec<ExchangeClient>) -> Self {
+        Self {
+            exchanges,
+            subscribers: 0,
+            unused_since: None,
+        }
+    }
+}
+
+/// Counts a request as a symbol & merger subscriber while alive.
+///
+/// The merger is removed once it has no subscribers. When lazily subscribing exchange clients
+/// are stopped after the grace period without subscribers.
+#[derive(Debug)]
+struct Subscriber {
+    key: MergerKey,
+    lazy_grace: Option<Duration>,
+    state: Arc<Mutex<State>>,
+    /// Grpc stream span.
+    span: tracing::Span,
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.span.in_scope(|| tracing::info!("stream ended"));
+        METRICS.grpc_streams.dec();
+        let mut state = self.state.lock().unwrap();
+        if let Some(shared) = state.mergers.get_mut(&self.key) {
+            shared.subscribers -= 1;
+            if shared.subscribers == 0 {
+                state.mergers.remove(&self.key);
+            }
+        }
+        let Some(clients) = state.clients.get_mut(&self.key.symbol) else {
+            return;
+        };
+        clients.subscribers -= 1;
+        let (0, Some(grace)) = (clients.subscribers, self.lazy_grace) else {
+            return;
+        };
+
+        let unused_since = Instant::now();
+        clients.unused_since = Some(unused_since);
+        let state = Arc::clone(&self.state);
+        let symbol = self.key.symbol.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            let mut state = state.lock().unwrap();
+            let unused = state
+                .clients
+                .get(&symbol)
+                .is_some_and(|c| c.unused_since == Some(unused_since));
+            if unused {
+                tracing::info!(%symbol, "unsubscribing");
+                state.clients.remove(&symbol);
+            }
+        });
+    }
+}
+
+/// [`SummaryMerger`] parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MergerKey {
+    symbol: Symbol,
+    /// Sorted included exchange names.
+    exchanges: Vec<&'static str>,
+    options: MergeOptions,
+}
+
+impl GrcServer {
+    /// Returns merged summaries receiver for the request, creating a new merger if necessary.
+    ///
+    /// Exchange clients are started if lazily subscribing & this is the symbol's first request.
+    #[allow(clippy::result_large_err)]
+    fn subscribe(
+        &self,
+        request: merged_order_book_protos::BookSummaryRequest,
+    ) -> Result<
+        (
+            tokio::sync::broadcast::Receiver<summary::Summary>,
+            Subscriber,
+        ),
+        Status,
+    > {
+        let symbol = match request.symbol.as_str() {
+            "" => self.default_symbol.clone(),
+            s => s
+                .parse()
+                .map_err(|err| Status::invalid_argument(format!("{err}")))?,
+        };
+        if !self.symbols.contains(&symbol) {
+            return Err(Status::not_found(format!(
+                "Symbol `{symbol}` not supported"
+            )));
+        }
+
+        let depth = match request.depth as usize {
+            0 => self.max_depth,
+            d if d > self.max_depth => {
+                return Err(Status::invalid_argument(format!(
+                    "depth must be at most {}",
+                    self.max_depth
+                )))
+            }
+            d => d,
+        };
+
+        if let Some(unknown) = request
+            .exchanges
+            .iter()
+            .find(|ex| !self.exchange_names.contains(&ex.as_str()))
+        {
+            return Err(Status::invalid_argument(format!(
+                "Exchange `{unknown}` not supported"
+            )));
+        }
+        let mut exchanges: Vec<_> = self
+            .exchange_names
+            .iter()
+            .filter(|name| {
+                request.exchanges.is_empty() || request.exchanges.contains(&name.to_string())
+            })
+            .copied()
+            .collect();
+        exchanges.sort_unstable();
+
+        let tick = match (request.tick_size.as_str(), request.tick_bps) {
+            ("", 0) => None,
+            (size, 0) => match size.parse() {
+                Ok(size) if size >= grouping::MIN_TICK_SIZE => Some(Tick::Size(size)),
+                Ok(size) if size > rust_decimal::Decimal::ZERO => {
+                    return Err(Status::invalid_argument(format!(
+                        "tick_size `{size}` must be at least {}",
+                        grouping::MIN_TICK_SIZE
+                    )))
+                }
+                _ => {
+                    return Err(Status::invalid_argument(format!(
+                        "Invalid tick_size `{size}`"
+                    )))
+                }
+            },
+            ("", bps) => Some(Tick::Bps(bps)),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Only one of tick_size & tick_bps may be set",
+                ))
+            }
+        };
+
+        let key = MergerKey {
+            symbol,
+            exchanges,
+            options: MergeOptions {
+                depth,
+                aggregate: request.aggregate,
+                tick,
+            },
+        };
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let clients = state
+            .clients
+            .entry(key.symbol.clone())
+            .or_insert_with(|| self.start_clients(&key.symbol));
+        clients.subscribers += 1;
+        clients.unused_since = None;
+
+        let shared = state.mergers.entry(key.clone()).or_insert_with(|| {
+            let clients: Vec<_> = clients
+                .exchanges
+                .iter()
+                .filter(|c| key.exchanges.contains(&c.name))
+                .collect();
+            SharedMerger {
+                merger: SummaryMerger::listen_to(&clients, key.options.clone()),
+                subscribers: 0,
+            }
+        });
+        shared.subscribers += 1;
+        let rx = shared.merger.tx.subscribe();
+
+        static STREAM_ID: AtomicU64 = AtomicU64::new(1);
+        let span = tracing::info_span!(
+            "book_summary",
+            id = STREAM_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            symbol = %key.symbol,
+            exchanges = ?key.exchanges,
+            depth = key.options.depth,
+            aggregate = key.options.aggregate,
+            tick = ?key.options.tick,
+        );
+        span.in_scope(|| tracing::info!("stream started"));
+
+        METRICS.grpc_streams.inc();
+        let subscriber = Subscriber {
+            key,
+            lazy_grace: self.lazy_grace,
+            state: Arc::clone(&self.state),
+            span,
+        };
+        Ok((rx, subscriber))
+    }
+
+    /// Starts exchange clients for a symbol's first request.
+    fn start_clients(&self, symbol: &Symbol) -> SymbolClients {
+        tracing::info!(%symbol, "subscribing");
+        let clients = exchanges(symbol, self.max_depth, &self.exchange_names)
+            .into_iter()
+            .map(|exchange| {
+                let (client, connected) = ExchangeClient::start(exchange);
+                tokio::spawn(async move {
+                    if let Err(err) = connected.await {
+                        tracing::error!("{err}");
+                    }
+                });
+                client
+            })
+            .collect();
+        SymbolClients::new(clients)
+    }
 }
 
 #[tonic::async_trait]
@@ -48,14 +464,31 @@ impl merged_order_book_protos::orderbook_aggregator_server::OrderbookAggregator
 
     async fn book_summary(
         &self,
-        _: tonic::Request<merged_order_book_protos::Empty>,
+        request: tonic::Request<merged_order_book_protos::BookSummaryRequest>,
     ) -> Result<tonic::Response<Self::BookSummaryStream>, tonic::Status> {
-        let rx = self.events.tx.subscribe();
+        let request = request.into_inner();
+        let include_latency = request.latency;
+        let (rx, subscriber) = self.subscribe(request)?;
 
-        let out = BroadcastStream::new(rx).filter_map(|r| {
+        let out = BroadcastStream::new(rx).filter_map(move |r| {
+            let _subscriber = &subscriber; // subscribed until the stream is dropped
             std::future::ready(match r {
-                Ok(r) => Some(Ok::<_, _>(r)),
-                _ => None, // ignore lagged messages
+                Ok(summary) => {
+                    let latency = summary.latency(SystemTime::now());
+                    if let Some(latency) = &latency {
+                        METRICS.observe_sent(latency);
+                    }
+                    let mut summary: merged_order_book_protos::Summary = summary.into();
+                    if include_latency {
+                        summary.latency = latency.map(<_>::from);
+                    }
+                    Some(Ok::<_, _>(summary))
+                }
+                Err(BroadcastStreamRecvError::Lagged(n)) => {
+                    // ignore lagged messages
+                    METRICS.grpc_lagged_summaries.inc_by(n);
+                    None
+                }
             })
         });
 
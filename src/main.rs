@@ -1,4 +1,15 @@
+use std::env;
+use tracing_subscriber::EnvFilter;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let filter = EnvFilter::try_from_env("LOG_LEVEL").unwrap_or_else(|_| EnvFilter::new("info"));
+    let log = tracing_subscriber::fmt().with_env_filter(filter);
+    match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => log.json().init(),
+        Ok("text") | Err(_) => log.init(),
+        Ok(format) => anyhow::bail!("Invalid LOG_FORMAT `{format}`, expected text or json"),
+    }
+
     merged_order_book::start().await
 }
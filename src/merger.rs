@@ -1,76 +1,198 @@
-use merged_order_book_protos::Summary;
-use std::sync::{Arc, Mutex};
+use crate::{
+    exchange::ExchangeClient,
+    grouping::{self, Tick},
+    metrics::METRICS,
+    summary::{AggregatedLevel, ExchangeInfo, Summary},
+};
+use rust_decimal::Decimal;
+use std::{
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 use tokio::sync::broadcast;
+use tracing::Instrument;
 
 /// Multiple same-currency summary merging broadcaster.
 ///
-/// Merges all summaries into a combined top 10.
+/// Merges all summaries into a combined top `depth`.
+/// Exchanges without updates for their [`ExchangeClient::stale_timeout`] are excluded
+/// & flagged as stale until fresh data arrives.
 #[derive(Debug)]
-pub struct Top10SummaryMerger {
+pub struct SummaryMerger {
     pub tx: broadcast::Sender<Summary>,
+    /// Exchange listener tasks, stopped when the merger is dropped.
+    tasks: Vec<tokio::task::JoinHandle<()>>,
 }
 
-impl Top10SummaryMerger {
-    /// Listen to multiple broadcaster merging and re-broadcasting.
+impl Drop for SummaryMerger {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        METRICS.summary_mergers.dec();
+    }
+}
+
+/// [`SummaryMerger`] output options.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MergeOptions {
+    /// Number of bids/asks.
+    pub depth: usize,
+    /// Also combine same price levels into aggregated bids/asks.
+    pub aggregate: bool,
+    /// Group aggregated bids/asks into price buckets, implies `aggregate`.
+    pub tick: Option<Tick>,
+}
+
+impl SummaryMerger {
+    /// Listen to multiple exchange broadcasters merging and re-broadcasting.
     ///
     /// Note: All summaries must be the same currencies.
-    pub fn listen_to(rx: Vec<broadcast::Receiver<Summary>>) -> Self {
+    pub fn listen_to(clients: &[&ExchangeClient], options: MergeOptions) -> Self {
         let (tx, _) = broadcast::channel(1);
 
-        let all = Arc::new(Mutex::new(vec![Summary::default(); rx.len()]));
+        // latest summary of each exchange, the last merged sequence number & tick bucket size
+        let all = Arc::new(Mutex::new((
+            vec![Summary::default(); clients.len()],
+            0,
+            None,
+        )));
 
-        for (idx, mut rx) in rx.into_iter().enumerate() {
+        let mut tasks = Vec::with_capacity(clients.len());
+        for (idx, client) in clients.iter().enumerate() {
             let all = Arc::clone(&all);
             let tx = tx.clone();
-            tokio::spawn(async move {
+            let options = options.clone();
+            let name = client.name;
+            let stale_timeout = client.stale_timeout;
+            let merge_observed = Arc::clone(&client.merge_observed);
+            let mut rx = client.tx.subscribe();
+            let span = tracing::info_span!(parent: None, "merger", exchange = name);
+            let listen = async move {
                 loop {
-                    match rx.recv().await {
-                        Ok(summary) => {
-                            let mut all = all.lock().unwrap();
-                            all[idx] = summary;
-                            _ = tx.send(merge_summaries(&all));
-                        }
-                        Err(broadcast::error::RecvError::Lagged(_)) => {}
-                        Err(broadcast::error::RecvError::Closed) => {
-                            eprintln!("channel closed");
+                    let next = match stale_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, rx.recv()).await,
+                        None => Ok(rx.recv().await),
+                    };
+                    let summary = match next {
+                        Ok(Ok(summary)) => summary,
+                        Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                        Ok(Err(broadcast::error::RecvError::Closed)) => {
+                            tracing::debug!("channel closed");
                             break;
                         }
+                        Err(_) => {
+                            let all = all.lock().unwrap();
+                            if all.0[idx].exchanges.iter().any(|info| info.stale) {
+                                continue; // already evicted
+                            }
+                            tracing::warn!("stale, evicting levels");
+                            stale_summary(name, &all.0[idx])
+                        }
+                    };
+
+                    let update = summary.exchanges.first().filter(|i| !i.stale).cloned();
+                    let mut all = all.lock().unwrap();
+                    let (summaries, sequence, tick_size) = &mut *all;
+                    summaries[idx] = summary;
+                    *sequence += 1;
+                    let merge_time = SystemTime::now();
+                    // observe each update once, by the first merger to merge it
+                    let received = update.as_ref().and_then(|u| u.receive_time).filter(|r| {
+                        let mut observed = merge_observed.lock().unwrap();
+                        let first = observed.is_none_or(|o| *r > o);
+                        if first {
+                            *observed = Some(*r);
+                        }
+                        first
+                    });
+                    if let Some(received) = received {
+                        let latency = merge_time.duration_since(received).unwrap_or_default();
+                        METRICS
+                            .merge_latency
+                            .with_label_values(&[name])
+                            .observe(latency.as_secs_f64());
                     }
+                    let merged = Summary {
+                        sequence: *sequence,
+                        merge_time: Some(merge_time),
+                        update,
+                        ..merge_summaries(summaries, &options, tick_size)
+                    };
+                    _ = tx.send(merged);
+                    METRICS.merged_summaries.inc();
                 }
-            });
+            };
+            tasks.push(tokio::spawn(listen.instrument(span)));
         }
 
-        Self { tx }
+        METRICS.summary_mergers.inc();
+        Self { tx, tasks }
     }
 }
 
-fn merge_summaries(sums: &[Summary]) -> Summary {
+/// Returns a summary without levels & with stale flagged exchange info.
+fn stale_summary(name: &'static str, last: &Summary) -> Summary {
+    let mut exchanges = last.exchanges.clone();
+    if exchanges.is_empty() {
+        exchanges.push(ExchangeInfo::new(name));
+    }
+    for info in &mut exchanges {
+        info.stale = true;
+    }
+    Summary {
+        exchanges,
+        ..<_>::default()
+    }
+}
+
+/// Merges exchange summaries.
+///
+/// `tick_size` is the last bucket size, kept while it cannot be determined, e.g. `Tick::Bps`
+/// without a mid price when a side is empty. Until a size is known aggregated levels are empty.
+fn merge_summaries(
+    sums: &[Summary],
+    options: &MergeOptions,
+    tick_size: &mut Option<Decimal>,
+) -> Summary {
     let mut merged = Summary::default();
     for s in sums {
         merged.bids.extend(s.bids.clone());
         merged.asks.extend(s.asks.clone());
+        merged.exchanges.extend(s.exchanges.clone());
     }
 
     // sort bids so highest price with highest amount is top
     merged.bids.sort_by(|a, b| {
         a.price
-            .total_cmp(&b.price)
+            .cmp(&b.price)
             .reverse()
-            .then(a.amount.total_cmp(&b.amount).reverse())
+            .then(a.amount.cmp(&b.amount).reverse())
     });
     // sort asks so lowest price with highest amount is top
     merged.asks.sort_by(|a, b| {
         a.price
-            .total_cmp(&b.price)
-            .then(a.amount.total_cmp(&b.amount).reverse())
+            .cmp(&b.price)
+            .then(a.amount.cmp(&b.amount).reverse())
     });
 
-    if !merged.bids.is_empty() && !merged.asks.is_empty() {
-        merged.spread = merged.asks[0].price - merged.bids[0].price;
+    if let Some(tick) = options.tick {
+        if let Some(size) = tick.size(&merged) {
+            *tick_size = Some(size);
+        }
+        if let Some(size) = *tick_size {
+            merged.aggregated_bids = grouping::group_bids(&merged.bids, size);
+            merged.aggregated_asks = grouping::group_asks(&merged.asks, size);
+        }
+    } else if options.aggregate {
+        merged.aggregated_bids = AggregatedLevel::aggregate(&merged.bids);
+        merged.aggregated_asks = AggregatedLevel::aggregate(&merged.asks);
     }
+    merged.aggregated_bids.truncate(options.depth);
+    merged.aggregated_asks.truncate(options.depth);
 
-    merged.bids.truncate(10);
-    merged.asks.truncate(10);
+    merged.bids.truncate(options.depth);
+    merged.asks.truncate(options.depth);
 
     merged
 }
@@ -0,0 +1,252 @@
+//! Prometheus metrics & `/metrics` http endpoint.
+
+use crate::summary::Latency;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Response, StatusCode,
+};
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, Mutex},
+    time::Instant,
+};
+
+/// Global metrics.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+const EXCHANGE_LABELS: &[&str] = &["exchange", "symbol"];
+
+/// Shared last message time of an exchange & symbol.
+pub type LastMessage = Arc<Mutex<Instant>>;
+
+pub struct Metrics {
+    registry: Registry,
+    /// Websocket messages received.
+    pub exchange_messages: IntCounterVec,
+    /// Websocket messages with an invalid format.
+    pub exchange_invalid_messages: IntCounterVec,
+    /// New connections after the first, including re-connect requests & rotations.
+    pub exchange_reconnects: IntCounterVec,
+    exchange_last_message_age: GaugeVec,
+    /// Last message time of each exchange & symbol, ages are set when gathering.
+    last_messages: Mutex<HashMap<(&'static str, String), LastMessage>>,
+    /// Merged summaries broadcast by all mergers.
+    pub merged_summaries: IntCounter,
+    /// Merged summaries not sent to grpc clients that fell behind.
+    pub grpc_lagged_summaries: IntCounter,
+    /// Active `BookSummary` streams.
+    pub grpc_streams: IntGauge,
+    /// Active summary mergers, shared by streams with the same parameters.
+    pub summary_mergers: IntGauge,
+    /// Exchange event to local receive latency.
+    pub exchange_latency: HistogramVec,
+    /// Local receive to merge latency.
+    pub merge_latency: HistogramVec,
+    grpc_send_latency: HistogramVec,
+    end_to_end_latency: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let exchange_messages = IntCounterVec::new(
+            Opts::new(
+                "exchange_messages_total",
+                "Exchange websocket messages received",
+            ),
+            EXCHANGE_LABELS,
+        )
+        .unwrap();
+        let exchange_invalid_messages = IntCounterVec::new(
+            Opts::new(
+                "exchange_invalid_messages_total",
+                "Exchange websocket messages with an invalid format",
+            ),
+            EXCHANGE_LABELS,
+        )
+        .unwrap();
+        let exchange_reconnects = IntCounterVec::new(
+            Opts::new(
+                "exchange_reconnects_total",
+                "Exchange websocket connections after the first",
+            ),
+            EXCHANGE_LABELS,
+        )
+        .unwrap();
+        let exchange_last_message_age = GaugeVec::new(
+            Opts::new(
+                "exchange_last_message_age_seconds",
+                "Time since the last exchange websocket message",
+            ),
+            EXCHANGE_LABELS,
+        )
+        .unwrap();
+        let merged_summaries = IntCounter::new(
+            "merged_summaries_total",
+            "Merged summaries broadcast by all mergers",
+        )
+        .unwrap();
+        let grpc_lagged_summaries = IntCounter::new(
+            "grpc_lagged_summaries_total",
+            "Merged summaries skipped by lagging grpc streams",
+        )
+        .unwrap();
+        let grpc_streams = IntGauge::new("grpc_streams", "Active BookSummary streams").unwrap();
+        let summary_mergers = IntGauge::new("summary_mergers", "Active summary mergers").unwrap();
+        let latency = |name: &str, help: &str, labels: &[&str]| {
+            // 0.5ms to ~8s
+            let buckets = prometheus::exponential_buckets(0.0005, 2.0, 15).unwrap();
+            HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets), labels).unwrap()
+        };
+        let exchange_latency = latency(
+            "exchange_latency_seconds",
+            "Exchange event to local receive latency",
+            EXCHANGE_LABELS,
+        );
+        let merge_latency = latency(
+            "merge_latency_seconds",
+            "Local receive to merged summary latency",
+            &["exchange"],
+        );
+        let grpc_send_latency = latency(
+            "grpc_send_latency_seconds",
+            "Merged summary to grpc send latency",
+            &["exchange"],
+        );
+        let end_to_end_latency = latency(
+            "end_to_end_latency_seconds",
+            "Exchange event, or local receive if unavailable, to grpc send latency",
+            &["exchange"],
+        );
+
+        registry
+            .register(Box::new(exchange_messages.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(exchange_invalid_messages.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(exchange_reconnects.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(exchange_last_message_age.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(merged_summaries.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(grpc_lagged_summaries.clone()))
+            .unwrap();
+        registry.register(Box::new(grpc_streams.clone())).unwrap();
+        registry
+            .register(Box::new(summary_mergers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(exchange_latency.clone()))
+            .unwrap();
+        registry.register(Box::new(merge_latency.clone())).unwrap();
+        registry
+            .register(Box::new(grpc_send_latency.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(end_to_end_latency.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            exchange_messages,
+            exchange_invalid_messages,
+            exchange_reconnects,
+            exchange_last_message_age,
+            last_messages: <_>::default(),
+            merged_summaries,
+            grpc_lagged_summaries,
+            grpc_streams,
+            summary_mergers,
+            exchange_latency,
+            merge_latency,
+            grpc_send_latency,
+            end_to_end_latency,
+        }
+    }
+
+    /// Observes the grpc send & end to end latency of a sent summary.
+    pub fn observe_sent(&self, latency: &Latency) {
+        self.grpc_send_latency
+            .with_label_values(&[latency.exchange])
+            .observe(latency.send.as_secs_f64());
+        self.end_to_end_latency
+            .with_label_values(&[latency.exchange])
+            .observe(latency.total().as_secs_f64());
+    }
+
+    /// Returns the last message time to update for an exchange & symbol.
+    pub fn last_message(&self, exchange: &'static str, symbol: &str) -> LastMessage {
+        let mut last_messages = self.last_messages.lock().unwrap();
+        let last = last_messages
+            .entry((exchange, symbol.to_owned()))
+            .or_insert_with(|| Arc::new(Mutex::new(Instant::now())));
+        Arc::clone(last)
+    }
+
+    /// Removes the last message time & age metric of an exchange & symbol, e.g. after unsubscribing.
+    ///
+    /// Nothing is removed if the entry has since been replaced by another client.
+    pub fn remove_last_message(&self, exchange: &'static str, symbol: &str, last: &LastMessage) {
+        let mut last_messages = self.last_messages.lock().unwrap();
+        let key = (exchange, symbol.to_owned());
+        if last_messages
+            .get(&key)
+            .is_some_and(|l| Arc::ptr_eq(l, last))
+        {
+            last_messages.remove(&key);
+            _ = self
+                .exchange_last_message_age
+                .remove_label_values(&[exchange, symbol]);
+        }
+    }
+
+    /// Returns all metrics in the prometheus text format.
+    pub fn encode(&self) -> String {
+        for ((exchange, symbol), last) in self.last_messages.lock().unwrap().iter() {
+            let age = last.lock().unwrap().elapsed();
+            self.exchange_last_message_age
+                .with_label_values(&[exchange, symbol])
+                .set(age.as_secs_f64());
+        }
+
+        let mut out = vec![];
+        _ = TextEncoder::new().encode(&self.registry.gather(), &mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
+
+/// Binds the metrics http server, returning a future serving `/metrics` forever.
+pub fn serve(port: u16) -> anyhow::Result<impl std::future::Future<Output = ()>> {
+    let server = hyper::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], port)))?.serve(
+        make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req| async move {
+                let response = match req.uri().path() {
+                    "/metrics" => Response::new(Body::from(METRICS.encode())),
+                    _ => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap(),
+                };
+                Ok::<_, Infallible>(response)
+            }))
+        }),
+    );
+
+    Ok(async move {
+        if let Err(err) = server.await {
+            tracing::error!("metrics server {err}");
+        }
+    })
+}
@@ -0,0 +1,228 @@
+//! okx exchange.
+
+use crate::{
+    book::LocalBook,
+    exchange::{Decoded, Decoder, Exchange},
+    summary::{ExchangeInfo, Summary},
+    symbol::Symbol,
+};
+use std::{
+    env,
+    time::{Duration, UNIX_EPOCH},
+};
+
+const EXCHANGE_NAME: &str = "okx";
+
+/// Levels included in the book checksum.
+const CHECKSUM_DEPTH: usize = 25;
+
+/// OKX books channel.
+///
+/// Maintains a local book from the snapshot & incremental updates, verifying
+/// `seqId` continuity & the CRC32 checksum, resubscribing on mismatch.
+#[derive(Debug)]
+pub struct Okx {
+    pub symbol: Symbol,
+    /// Bids/asks depth, the books channel provides at most 400.
+    pub depth: usize,
+}
+
+impl Okx {
+    /// OKX instrument id, e.g. `ETH-BTC`.
+    fn inst_id(&self) -> String {
+        format!("{}-{}", self.symbol.base, self.symbol.quote).to_ascii_uppercase()
+    }
+
+    /// Books channel subscribe or unsubscribe message.
+    fn channel_message(&self, op: &str) -> String {
+        serde_json::json!({
+            "op": op,
+            "args": [{"channel": "books", "instId": self.inst_id()}],
+        })
+        .to_string()
+    }
+}
+
+impl Exchange for Okx {
+    fn name(&self) -> &'static str {
+        EXCHANGE_NAME
+    }
+
+    fn symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+
+    fn url(&self) -> String {
+        env::var("OKX_URL").unwrap_or_else(|_| "wss://ws.okx.com:8443/ws/v5/public".into())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![self.channel_message("subscribe")]
+    }
+
+    fn unsubscribe_messages(&self) -> Vec<String> {
+        vec![self.channel_message("unsubscribe")]
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(BooksDecoder {
+            depth: self.depth,
+            book: <_>::default(),
+            seq_id: None,
+            resubscribing: false,
+        })
+    }
+}
+
+/// Maintains a local book from books channel snapshots & updates.
+///
+/// See <https://www.okx.com/docs-v5/en/#order-book-trading-market-data-ws-order-book-channel>.
+#[derive(Debug)]
+struct BooksDecoder {
+    depth: usize,
+    book: LocalBook,
+    /// Last applied `seqId`, `None` if a new snapshot is required.
+    seq_id: Option<i64>,
+    /// Whether a resubscribe has been requested & not yet answered with a snapshot.
+    resubscribing: bool,
+}
+
+impl BooksDecoder {
+    /// Discards the local book, requesting a resubscribe unless already requested.
+    fn resubscribe(&mut self) -> Option<Decoded> {
+        self.book.clear();
+        self.seq_id = None;
+        (!std::mem::replace(&mut self.resubscribing, true)).then_some(Decoded::Resubscribe)
+    }
+}
+
+#[async_trait::async_trait]
+impl Decoder for BooksDecoder {
+    async fn decode(&mut self, json: &str) -> Result<Option<Decoded>, ()> {
+        let Ok(msg) = serde_json::from_str::<BooksMessage>(json) else {
+            return Ok(decode_event(json));
+        };
+        let Some(data) = msg.data.into_iter().next() else {
+            return Err(());
+        };
+
+        match msg.action.as_str() {
+            "snapshot" => {
+                self.book.clear();
+                self.resubscribing = false;
+            }
+            "update" => match self.seq_id {
+                Some(seq_id) if seq_id == data.prev_seq_id => {}
+                // updates after a discarded book, until the resubscribe snapshot
+                None => return Ok(self.resubscribe()),
+                Some(seq_id) => {
+                    tracing::warn!(
+                        seq_id,
+                        prev_seq_id = data.prev_seq_id,
+                        "books sequence gap, resubscribing"
+                    );
+                    return Ok(self.resubscribe());
+                }
+            },
+            _ => return Ok(None),
+        }
+
+        let updated = self
+            .book
+            .update_bids(&levels(&data.bids))
+            .and_then(|_| self.book.update_asks(&levels(&data.asks)));
+        if updated.is_err() {
+            self.book.clear();
+            self.seq_id = None;
+            return Err(());
+        }
+        self.seq_id = Some(data.seq_id);
+
+        let checksum = checksum(&self.book.summary(EXCHANGE_NAME, CHECKSUM_DEPTH));
+        if checksum != data.checksum {
+            tracing::warn!(
+                expected = data.checksum,
+                checksum,
+                "books checksum mismatch, resubscribing"
+            );
+            return Ok(self.resubscribe());
+        }
+
+        let info = ExchangeInfo {
+            event_time: data
+                .ts
+                .parse()
+                .ok()
+                .map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+            sequence: u64::try_from(data.seq_id).ok(),
+            ..ExchangeInfo::new(EXCHANGE_NAME)
+        };
+        let summary = self.book.summary(EXCHANGE_NAME, self.depth);
+        Ok(Some(Decoded::Summary(summary.with_info(info))))
+    }
+}
+
+/// Returns `[price, size]` levels from `[price, size, _, orders]` levels.
+fn levels(levels: &[[String; 4]]) -> Vec<[String; 2]> {
+    levels
+        .iter()
+        .map(|[price, size, ..]| [price.clone(), size.clone()])
+        .collect()
+}
+
+/// Signed CRC32 of the top 25 bids & asks interleaved, `bid:size:ask:size:...`.
+fn checksum(book: &Summary) -> i32 {
+    let mut values = vec![];
+    for idx in 0..CHECKSUM_DEPTH {
+        for level in [book.bids.get(idx), book.asks.get(idx)]
+            .into_iter()
+            .flatten()
+        {
+            values.push(level.price.to_string());
+            values.push(level.amount.to_string());
+        }
+    }
+    crc32fast::hash(values.join(":").as_bytes()) as i32
+}
+
+/// Decodes non-book events, an `error` event is a subscription error.
+fn decode_event(json: &str) -> Option<Decoded> {
+    let event: Event = serde_json::from_str(json).ok()?;
+    match event.event.as_str() {
+        "error" => Some(Decoded::SubscriptionError(format!(
+            "{} (code {})",
+            event.msg.unwrap_or_default(),
+            event.code.unwrap_or_default()
+        ))),
+        _ => None,
+    }
+}
+
+/// Operation response event, e.g. `subscribe` or `error`.
+#[derive(Debug, serde::Deserialize)]
+struct Event {
+    pub event: String,
+    pub code: Option<String>,
+    pub msg: Option<String>,
+}
+
+/// Books channel snapshot or update.
+#[derive(Debug, serde::Deserialize)]
+struct BooksMessage {
+    pub action: String,
+    pub data: Vec<BooksData>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BooksData {
+    pub bids: Vec<[String; 4]>,
+    pub asks: Vec<[String; 4]>,
+    /// Event time, unix milliseconds.
+    pub ts: String,
+    pub checksum: i32,
+    #[serde(rename = "seqId")]
+    pub seq_id: i64,
+    /// `seqId` of the previous message, `-1` for snapshots.
+    #[serde(rename = "prevSeqId")]
+    pub prev_seq_id: i64,
+}
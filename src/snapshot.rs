@@ -0,0 +1,67 @@
+//! REST order book snapshots of local book decoders.
+
+use crate::{
+    backoff::Backoff,
+    exchange::{env_millis, reconnect_backoff},
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// REST snapshot http client.
+///
+/// Requests time out after `SNAPSHOT_TIMEOUT_MS`, so a stalled request cannot block the
+/// connection's read loop. Failed snapshots are retried after the re-connect backoff delays,
+/// rather than on every following message, to stay within exchange rate limits.
+#[derive(Debug)]
+pub struct SnapshotClient {
+    http: reqwest::Client,
+    backoff: Backoff,
+    /// Time before which snapshots should not be retried after a failure.
+    retry_at: Option<Instant>,
+}
+
+impl SnapshotClient {
+    pub fn new(exchange_name: &str) -> Self {
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout) = env_millis(exchange_name, "SNAPSHOT_TIMEOUT_MS", 10_000) {
+            http = http.timeout(timeout);
+        }
+        let reconnect = reconnect_backoff(exchange_name);
+        Self {
+            http: http.build().expect("snapshot http client"),
+            // keep retrying, there is no connection to give up on
+            backoff: Backoff::new(reconnect.initial, reconnect.max, None),
+            retry_at: None,
+        }
+    }
+
+    /// Fetches a json snapshot.
+    pub async fn get<T: serde::de::DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        Ok(self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Returns `true` if a failed snapshot should not be retried yet.
+    pub fn throttled(&self) -> bool {
+        self.retry_at.is_some_and(|at| Instant::now() < at)
+    }
+
+    /// Delays the next snapshot after a failure, returning the delay.
+    pub fn failed(&mut self) -> Duration {
+        let delay = self.backoff.next_delay().unwrap_or_default();
+        self.retry_at = Some(Instant::now() + delay);
+        delay
+    }
+
+    /// Resets the retry delay after a successful snapshot.
+    pub fn reset(&mut self) {
+        self.backoff.reset();
+        self.retry_at = None;
+    }
+}
@@ -0,0 +1,62 @@
+//! Exchange startup policy.
+
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use std::{future::Future, str::FromStr};
+
+/// Number of exchanges that must connect for startup to succeed.
+///
+/// Exchanges that fail to connect initially continue to re-connect & join
+/// merged summaries once connected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartupPolicy {
+    /// All exchanges must connect.
+    #[default]
+    All,
+    /// Most exchanges must connect.
+    Quorum,
+    /// At least one exchange must connect.
+    Any,
+}
+
+impl StartupPolicy {
+    /// Awaits exchange connected futures until the policy is satisfied.
+    pub async fn await_connected<F>(self, connected: Vec<F>) -> anyhow::Result<()>
+    where
+        F: Future<Output = anyhow::Result<()>>,
+    {
+        let required = match self {
+            Self::All => connected.len(),
+            Self::Quorum => connected.len() / 2 + 1,
+            Self::Any => 1.min(connected.len()),
+        };
+        let mut failures = connected.len() - required;
+
+        let mut connected: FuturesUnordered<_> = connected.into_iter().collect();
+        let mut connected_count = 0;
+        while connected_count < required {
+            match connected.next().await {
+                Some(Ok(_)) => connected_count += 1,
+                Some(Err(err)) if failures == 0 => return Err(err),
+                Some(Err(err)) => {
+                    tracing::warn!("{err}, continuing startup without it");
+                    failures -= 1;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for StartupPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(Self::All),
+            "quorum" => Ok(Self::Quorum),
+            "any" => Ok(Self::Any),
+            _ => anyhow::bail!("Invalid STARTUP_POLICY `{s}`, expected all, quorum or any"),
+        }
+    }
+}
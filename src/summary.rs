@@ -0,0 +1,259 @@
+//! Internal order book summary model.
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Exchange or merged top bids/asks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    /// Top bids with same price, or price bucket, levels combined if requested.
+    pub aggregated_bids: Vec<AggregatedLevel>,
+    /// Top asks with same price, or price bucket, levels combined if requested.
+    pub aggregated_asks: Vec<AggregatedLevel>,
+    /// Latest update info of each exchange included.
+    pub exchanges: Vec<ExchangeInfo>,
+    /// Merged summary sequence number, zero for exchange summaries.
+    pub sequence: u64,
+    /// Merged summary creation time, `None` for exchange summaries.
+    pub merge_time: Option<SystemTime>,
+    /// Exchange update that produced this merged summary, `None` for exchange summaries.
+    pub update: Option<ExchangeInfo>,
+}
+
+impl Summary {
+    /// Parses exchange `[price, amount]` bids & asks.
+    pub fn parse(
+        exchange: &'static str,
+        bids: &[[String; 2]],
+        asks: &[[String; 2]],
+    ) -> Result<Self, ()> {
+        Ok(Self {
+            bids: bids
+                .iter()
+                .map(|b| (exchange, b).try_into())
+                .collect::<Result<_, _>>()?,
+            asks: asks
+                .iter()
+                .map(|a| (exchange, a).try_into())
+                .collect::<Result<_, _>>()?,
+            ..<_>::default()
+        })
+    }
+
+    /// Sets the exchange update info.
+    pub fn with_info(mut self, info: ExchangeInfo) -> Self {
+        self.exchanges = vec![info];
+        self
+    }
+
+    /// Returns the latency of the update that produced this merged summary, if sent at `sent`.
+    pub fn latency(&self, sent: SystemTime) -> Option<Latency> {
+        let update = self.update.as_ref()?;
+        let received = update.receive_time?;
+        let merged = self.merge_time?;
+        Some(Latency {
+            exchange: update.exchange,
+            receive: update
+                .event_time
+                .and_then(|event| received.duration_since(event).ok()),
+            merge: merged.duration_since(received).unwrap_or_default(),
+            send: sent.duration_since(merged).unwrap_or_default(),
+        })
+    }
+
+    /// Returns top ask price minus top bid price, zero if either are empty.
+    pub fn spread(&self) -> Decimal {
+        match (self.asks.first(), self.bids.first()) {
+            (Some(ask), Some(bid)) => ask.price - bid.price,
+            _ => Decimal::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Level {
+    pub exchange: &'static str,
+    pub price: Decimal,
+    pub amount: Decimal,
+}
+
+/// Exchange book update timing & sequencing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExchangeInfo {
+    pub exchange: &'static str,
+    /// Exchange event time, if provided by the exchange.
+    pub event_time: Option<SystemTime>,
+    /// Local websocket message receive time.
+    pub receive_time: Option<SystemTime>,
+    /// Exchange sequence or update id, if provided by the exchange.
+    pub sequence: Option<u64>,
+    /// No recent updates, levels have been evicted from the merged summary.
+    pub stale: bool,
+}
+
+impl ExchangeInfo {
+    pub fn new(exchange: &'static str) -> Self {
+        Self {
+            exchange,
+            event_time: None,
+            receive_time: None,
+            sequence: None,
+            stale: false,
+        }
+    }
+}
+
+/// Latency breakdown of an exchange update through to grpc send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    pub exchange: &'static str,
+    /// Exchange event to local receive, if the exchange provides event times.
+    pub receive: Option<Duration>,
+    /// Local receive to merge.
+    pub merge: Duration,
+    /// Merge to grpc send.
+    pub send: Duration,
+}
+
+impl Latency {
+    /// Total latency from the exchange event, or local receive if not available.
+    pub fn total(&self) -> Duration {
+        self.receive.unwrap_or_default() + self.merge + self.send
+    }
+}
+
+/// Combined levels of multiple exchanges at the same price or price bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregatedLevel {
+    pub price: Decimal,
+    /// Total amount of all `levels`.
+    pub amount: Decimal,
+    pub levels: Vec<Level>,
+}
+
+impl AggregatedLevel {
+    /// Combines sorted levels with the same price.
+    pub fn aggregate(levels: &[Level]) -> Vec<Self> {
+        Self::group(levels, |price| price)
+    }
+
+    /// Combines sorted levels with the same `bucket(price)`, summing amounts per exchange.
+    pub fn group(levels: &[Level], bucket: impl Fn(Decimal) -> Decimal) -> Vec<Self> {
+        let mut grouped: Vec<Self> = vec![];
+        for level in levels {
+            let price = bucket(level.price);
+            let agg = match grouped.last_mut() {
+                Some(agg) if agg.price == price => agg,
+                _ => {
+                    grouped.push(Self {
+                        price,
+                        amount: Decimal::ZERO,
+                        levels: vec![],
+                    });
+                    grouped.last_mut().unwrap()
+                }
+            };
+            agg.amount += level.amount;
+            match agg.levels.iter_mut().find(|l| l.exchange == level.exchange) {
+                Some(l) => l.amount += level.amount,
+                None => agg.levels.push(Level {
+                    price,
+                    ..level.clone()
+                }),
+            }
+        }
+        grouped
+    }
+}
+
+impl TryFrom<(&'static str, &[String; 2])> for Level {
+    type Error = ();
+
+    fn try_from((exchange, price_amount): (&'static str, &[String; 2])) -> Result<Self, ()> {
+        let price = price_amount[0].parse().map_err(|_| ())?;
+        let amount = price_amount[1].parse().map_err(|_| ())?;
+        Ok(Self {
+            exchange,
+            price,
+            amount,
+        })
+    }
+}
+
+impl From<Summary> for merged_order_book_protos::Summary {
+    fn from(summary: Summary) -> Self {
+        let spread = summary.spread();
+        Self {
+            spread: spread.to_f64().unwrap_or_default(),
+            spread_exact: spread.to_string(),
+            bids: summary.bids.into_iter().map(<_>::from).collect(),
+            asks: summary.asks.into_iter().map(<_>::from).collect(),
+            aggregated_bids: summary.aggregated_bids.into_iter().map(<_>::from).collect(),
+            aggregated_asks: summary.aggregated_asks.into_iter().map(<_>::from).collect(),
+            sequence: summary.sequence,
+            merge_time_us: summary.merge_time.map(unix_micros).unwrap_or_default(),
+            exchanges: summary.exchanges.into_iter().map(<_>::from).collect(),
+            latency: None,
+        }
+    }
+}
+
+impl From<Latency> for merged_order_book_protos::Latency {
+    fn from(latency: Latency) -> Self {
+        let receive_us = latency.receive.unwrap_or_default().as_micros() as u64;
+        let merge_us = latency.merge.as_micros() as u64;
+        let send_us = latency.send.as_micros() as u64;
+        Self {
+            exchange: latency.exchange.into(),
+            receive_us,
+            merge_us,
+            send_us,
+            // sum of the truncated parts so they always add up
+            total_us: receive_us + merge_us + send_us,
+        }
+    }
+}
+
+impl From<ExchangeInfo> for merged_order_book_protos::ExchangeInfo {
+    fn from(info: ExchangeInfo) -> Self {
+        Self {
+            exchange: info.exchange.into(),
+            event_time_us: info.event_time.map(unix_micros).unwrap_or_default(),
+            receive_time_us: info.receive_time.map(unix_micros).unwrap_or_default(),
+            sequence: info.sequence.unwrap_or_default(),
+            stale: info.stale,
+        }
+    }
+}
+
+fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as _
+}
+
+impl From<AggregatedLevel> for merged_order_book_protos::AggregatedLevel {
+    fn from(level: AggregatedLevel) -> Self {
+        Self {
+            price: level.price.to_f64().unwrap_or_default(),
+            amount: level.amount.to_f64().unwrap_or_default(),
+            price_exact: level.price.to_string(),
+            amount_exact: level.amount.to_string(),
+            levels: level.levels.into_iter().map(<_>::from).collect(),
+        }
+    }
+}
+
+impl From<Level> for merged_order_book_protos::Level {
+    fn from(level: Level) -> Self {
+        Self {
+            exchange: level.exchange.into(),
+            price: level.price.to_f64().unwrap_or_default(),
+            amount: level.amount.to_f64().unwrap_or_default(),
+            price_exact: level.price.to_string(),
+            amount_exact: level.amount.to_string(),
+        }
+    }
+}
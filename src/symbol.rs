@@ -0,0 +1,44 @@
+//! Canonical trading pair symbols.
+
+use std::{fmt, str::FromStr};
+
+/// A trading pair, e.g. `eth/btc`.
+///
+/// Parsed case-insensitively from `BASE/QUOTE`, `BASE-QUOTE` or `BASE_QUOTE`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    pub base: String,
+    pub quote: String,
+}
+
+impl Symbol {
+    /// Lowercase concatenated pair, e.g. `ethbtc`.
+    pub fn concat_lower(&self) -> String {
+        format!("{}{}", self.base, self.quote)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, quote) = s
+            .trim()
+            .split_once(['/', '-', '_'])
+            .ok_or_else(|| anyhow::anyhow!("Invalid symbol `{s}`, expected e.g. `eth/btc`"))?;
+        anyhow::ensure!(
+            !base.is_empty() && !quote.is_empty(),
+            "Invalid symbol `{s}`, expected e.g. `eth/btc`"
+        );
+        Ok(Self {
+            base: base.to_ascii_lowercase(),
+            quote: quote.to_ascii_lowercase(),
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the binance diff depth stream local book.
+///
+/// Asserts the local book is built from a REST snapshot & diff updates,
+/// and is resynced after an update sequence gap, retrying failed snapshots with backoff.
+#[tokio::test]
+async fn binance_diff_depth() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BINANCE_REST_URL", binance.rest_url());
+    env::set_var("BINANCE_DIFF_DEPTH", "true");
+    env::set_var("BINANCE_RECONNECT_DELAY_MS", "200");
+    env::set_var("BINANCE_RECONNECT_MAX_DELAY_MS", "800");
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["binance".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "binance", 0.071389, 10.5);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "binance", 0.071438, 14.56878);
+    assert_level_eq!(msg.asks[1], "binance", 0.0715, 2.5);
+    assert!(binance.snapshot_requests() >= 1);
+
+    // change the book, but drop the update causing a gap
+    let snapshots = binance.snapshot_requests();
+    binance.drop_next_update();
+    binance.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(), // new
+            ["0.07140100", "23.30750000"].into(),
+        ], // removed 0.07138900
+        asks: vec![
+            ["0.07143800", "4.00000000"].into(), // changed
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    // await the resynced book
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[0].price == 0.07143 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "binance", 0.07143, 10.5);
+    assert_level_eq!(msg.bids[1], "binance", 0.071401, 23.3075);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "binance", 0.071438, 4.0);
+    assert_level_eq!(msg.asks[1], "binance", 0.0715, 2.5);
+    assert!(
+        binance.snapshot_requests() > snapshots,
+        "gap did not resync"
+    );
+
+    // cause another gap, while snapshots are rate limited
+    binance.set_snapshots_failing(true);
+    let snapshots = binance.snapshot_requests();
+    binance.drop_next_update();
+    binance.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(),
+            ["0.07140100", "23.30750000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "4.00000000"].into(),
+            ["0.07150000", "3.00000000"].into(), // changed
+        ],
+    });
+
+    // retried after ~200ms, ~400ms, ~800ms.. rather than on every ~100ms update
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let failed = binance.snapshot_requests() - snapshots;
+    assert!(
+        (1..=4).contains(&failed),
+        "{failed} snapshot requests in 1s"
+    );
+
+    binance.set_snapshots_failing(false);
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.asks[1].amount == 3.0 {
+            break next;
+        }
+        assert!(
+            a.elapsed() < TEST_WAIT,
+            "did not resync after snapshot failures"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_level_eq!(msg.asks[1], "binance", 0.0715, 3.0);
+}
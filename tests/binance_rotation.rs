@@ -0,0 +1,69 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{env, time::Instant};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for proactive binance connection rotation.
+///
+/// Asserts connections are replaced on an interval, with the new connection
+/// used before the old one is closed so there is no gap in updates,
+/// & rotations are counted as re-connects.
+#[tokio::test]
+async fn binance_rotation() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("BINANCE_ROTATE_INTERVAL_MS", "500");
+    env::set_var("BINANCE_STALE_TIMEOUT_MS", "300");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["binance".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    // binance levels are continuously provided during rotations
+    let a = Instant::now();
+    while binance.connections() < 3 {
+        let msg = stream.message().await.unwrap().expect("stream closed");
+        assert_eq!(msg.bids.len(), 1, "{msg:#?}");
+        assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+        assert!(msg.exchanges.iter().all(|info| !info.stale));
+        assert!(binance.open_connections() <= 2);
+        assert!(a.elapsed() < TEST_WAIT, "binance not rotated");
+    }
+
+    let metrics = reqwest::get(format!(
+        "http://localhost:{}/metrics",
+        env::var("METRICS_PORT").unwrap()
+    ))
+    .await
+    .expect("metrics")
+    .text()
+    .await
+    .unwrap();
+    let prefix = r#"exchange_reconnects_total{exchange="binance",symbol="eth/btc"} "#;
+    let reconnects: u64 = metrics
+        .lines()
+        .find_map(|l| l.strip_prefix(prefix))
+        .expect("missing exchange_reconnects_total")
+        .parse()
+        .unwrap();
+    assert!(reconnects >= 2, "{reconnects} reconnects");
+}
@@ -0,0 +1,115 @@
+use crate::util::{bitfinex::MockBitfinex, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the bitfinex book channel local book.
+///
+/// Asserts the local book is built from the positional snapshot & level updates,
+/// with signed amounts for bids/asks, and is re-connected after a checksum mismatch.
+#[tokio::test]
+async fn bitfinex_checksum() {
+    let bitfinex = MockBitfinex::start();
+    bitfinex.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    env::set_var("EXCHANGES", "bitfinex");
+    env::set_var("BITFINEX_URL", bitfinex.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "bitfinex", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "bitfinex", 0.071389, 10.5);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "bitfinex", 0.071438, 14.56878);
+    assert_level_eq!(msg.asks[1], "bitfinex", 0.0715, 2.5);
+    assert_eq!(bitfinex.subscriptions(), 1);
+
+    // change the book, applying level updates & removals
+    bitfinex.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(), // new
+            ["0.07140100", "23.30750000"].into(),
+        ], // removed 0.07138900
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[0].price == 0.07143 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "bitfinex", 0.07143, 10.5);
+    assert_level_eq!(msg.bids[1], "bitfinex", 0.071401, 23.3075);
+    assert_eq!(msg.asks.len(), 2);
+
+    // change the book, with a bad checksum
+    bitfinex.corrupt_next_checksum();
+    bitfinex.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(),
+            ["0.07140100", "23.30750000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "4.00000000"].into(), // changed
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    // await the re-connected book
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.asks[0].amount == 4.0 && bitfinex.subscriptions() == 2 {
+            break next;
+        }
+        assert!(
+            a.elapsed() < TEST_WAIT,
+            "checksum mismatch did not re-connect"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "bitfinex", 0.07143, 10.5);
+    assert_level_eq!(msg.bids[1], "bitfinex", 0.071401, 23.3075);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "bitfinex", 0.071438, 4.0);
+    assert_level_eq!(msg.asks[1], "bitfinex", 0.0715, 2.5);
+    assert_eq!(bitfinex.connections(), 2);
+}
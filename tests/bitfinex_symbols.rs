@@ -0,0 +1,55 @@
+use crate::util::{bitfinex::MockBitfinex, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for bitfinex trading pair symbols.
+///
+/// Asserts symbols are subscribed with bitfinex currency codes, e.g. `UST` for USDT,
+/// & a `:` separator for currencies longer than 3 characters.
+#[tokio::test]
+async fn bitfinex_symbols() {
+    let bitfinex = MockBitfinex::start();
+    bitfinex.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    env::set_var("EXCHANGES", "bitfinex");
+    env::set_var("BITFINEX_URL", bitfinex.url());
+    env::set_var("SYMBOLS", "eth/btc,btc/usdt,sol/usdc,doge/usd");
+    let mut client = util::start_server().await;
+
+    for symbol in ["btc/usdt", "sol/usdc"] {
+        let mut stream = client
+            .book_summary(merged_order_book_protos::BookSummaryRequest {
+                symbol: symbol.into(),
+                ..<_>::default()
+            })
+            .await
+            .expect("book_summary")
+            .into_inner();
+        let msg = stream.message().await.unwrap().expect("stream closed");
+
+        eprintln!("{symbol} {msg:#?}");
+
+        assert_eq!(msg.bids.len(), 1);
+        assert_level_eq!(msg.bids[0], "bitfinex", 0.071401, 23.3075);
+    }
+
+    let a = Instant::now();
+    let mut symbols = loop {
+        let symbols = bitfinex.subscribed_symbols();
+        if symbols.len() == 4 {
+            break symbols;
+        }
+        assert!(a.elapsed() < TEST_WAIT, "{symbols:?}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    symbols.sort();
+    assert_eq!(symbols, ["tBTCUST", "tDOGE:USD", "tETHBTC", "tSOLUDC"]);
+}
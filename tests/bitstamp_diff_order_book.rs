@@ -0,0 +1,93 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the bitstamp diff order book channel local book.
+///
+/// Asserts the local book is built from a REST snapshot & subsequent diffs.
+#[tokio::test]
+async fn bitstamp_diff_order_book() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![
+            ["0.07138988", "0.60000000"].into(),
+            ["0.07138900", "1.20000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143677", "2.56878000"].into(),
+            ["0.07150000", "10.10000000"].into(),
+        ],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("BITSTAMP_REST_URL", bitstamp.rest_url());
+    env::set_var("BITSTAMP_DIFF_ORDER_BOOK", "true");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["bitstamp".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "bitstamp", 0.07138988, 0.6);
+    assert_level_eq!(msg.bids[1], "bitstamp", 0.071389, 1.2);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "bitstamp", 0.07143677, 2.56878);
+    assert_level_eq!(msg.asks[1], "bitstamp", 0.0715, 10.1);
+    assert_eq!(bitstamp.snapshot_requests(), 1);
+
+    bitstamp.set_orders(OrderBook {
+        bids: vec![
+            ["0.07138988", "0.60000000"].into(),
+            ["0.07138900", "1.20000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143300", "10.10000000"].into(), // new
+            ["0.07143677", "1.00000000"].into(),  // changed
+        ], // removed 0.07150000
+    });
+
+    // await the diff
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if (&next.bids, &next.asks) != (&msg.bids, &msg.asks) {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "bitstamp", 0.07138988, 0.6);
+    assert_level_eq!(msg.bids[1], "bitstamp", 0.071389, 1.2);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "bitstamp", 0.071433, 10.1);
+    assert_level_eq!(msg.asks[1], "bitstamp", 0.07143677, 1.0);
+
+    // diffs are applied without further snapshots
+    assert_eq!(bitstamp.snapshot_requests(), 1);
+}
@@ -0,0 +1,31 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook};
+use std::env;
+
+mod util;
+
+/// Scenario test for bitstamp `bts:error` subscription events.
+///
+/// Asserts a rejected subscription fails startup with the error.
+#[tokio::test]
+async fn bitstamp_subscription_error() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+    let bitstamp = MockBitstamp::start();
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("SYMBOLS", "eth/usd"); // mock bitstamp only supports eth/btc
+    env::set_var("GRPC_PORT", util::random_open_port().await.to_string());
+
+    let err = merged_order_book::start()
+        .await
+        .expect_err("startup should fail");
+
+    assert_eq!(
+        err.to_string(),
+        "bitstamp subscription failed: Bad subscription string."
+    );
+}
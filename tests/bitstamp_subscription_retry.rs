@@ -0,0 +1,72 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for bitstamp `bts:error` subscription events with the `any` startup policy.
+///
+/// Asserts the server starts without bitstamp, which keeps re-connecting
+/// & joins merged summaries once its subscription succeeds.
+#[tokio::test]
+async fn bitstamp_subscription_retry() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+    bitstamp.set_rejecting(true);
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("BITSTAMP_RECONNECT_DELAY_MS", "50");
+    env::set_var("BITSTAMP_RECONNECT_MAX_DELAY_MS", "100");
+    env::set_var("STARTUP_POLICY", "any");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 1);
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+
+    // rejected subscriptions are retried
+    let a = Instant::now();
+    while bitstamp.connections() < 3 {
+        assert!(a.elapsed() < TEST_WAIT, "bitstamp subscription not retried");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    bitstamp.set_rejecting(false);
+
+    // await bitstamp joining
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 2 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT, "bitstamp not joined");
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "bitstamp", 0.07138988, 0.6);
+}
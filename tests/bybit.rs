@@ -0,0 +1,169 @@
+use crate::util::{binance::MockBinance, bybit::MockBybit, OrderBook, TEST_WAIT};
+use approx::assert_relative_eq;
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the bybit orderbook stream.
+///
+/// Asserts that binance & bybit order book streams are listened
+/// to & merged properly into the grpc interface, bybit update id resets
+/// are followed & application level pings keep the connection open.
+#[tokio::test]
+async fn bybit() {
+    // start and setup binance & bybit ws
+    const HIGH_BID: &str = "0.07140100";
+    const HIGH_BID_F: f64 = 0.07140100;
+    const MID_BID: &str = "0.07138988";
+    const MID_BID_F: f64 = 0.07138988;
+    const LOW_BID: &str = "0.07138900";
+    const LOW_BID_F: f64 = 0.07138900;
+
+    const LOW_ASK: &str = "0.07143677";
+    const LOW_ASK_F: f64 = 0.07143677;
+    const MID_ASK: &str = "0.07143800";
+    const MID_ASK_F: f64 = 0.07143800;
+    const HIGH_ASK: &str = "0.07150000";
+    const HIGH_ASK_F: f64 = 0.07150000;
+
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![
+            [HIGH_BID, "23.30750000"].into(),
+            [LOW_BID, "10.50000000"].into(),
+        ],
+        asks: vec![
+            [MID_ASK, "14.56878000"].into(),
+            [HIGH_ASK, "2.50000000"].into(),
+        ],
+    });
+
+    let bybit = MockBybit::start();
+    bybit.set_orders(OrderBook {
+        bids: vec![
+            [MID_BID, "0.60000000"].into(),
+            [LOW_BID, "1.20000000"].into(),
+        ],
+        asks: vec![
+            [LOW_ASK, "2.56878000"].into(),
+            [HIGH_ASK, "10.10000000"].into(),
+        ],
+    });
+
+    // configure & start grpc server
+    env::set_var("EXCHANGES", "binance,bybit");
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BYBIT_URL", bybit.url());
+    env::set_var("BYBIT_PING_INTERVAL_MS", "100");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            symbol: "ETH/BTC".into(),
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    // await a message with both exchanges inside
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap();
+        let next = next.expect("stream closed");
+
+        if next.bids.iter().any(|b| b.exchange == "binance")
+            && next.bids.iter().any(|b| b.exchange == "bybit")
+        {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    let bids = &msg.bids;
+    assert_level_eq!(bids[0], "binance", HIGH_BID_F, 23.3075);
+    assert_level_eq!(bids[1], "bybit", MID_BID_F, 0.6);
+    assert_level_eq!(bids[2], "binance", LOW_BID_F, 10.5);
+    assert_level_eq!(bids[3], "bybit", LOW_BID_F, 1.2);
+
+    let asks = &msg.asks;
+    assert_level_eq!(asks[0], "bybit", LOW_ASK_F, 2.56878);
+    assert_level_eq!(asks[1], "binance", MID_ASK_F, 14.56878);
+    assert_level_eq!(asks[2], "bybit", HIGH_ASK_F, 10.1);
+    assert_level_eq!(asks[3], "binance", HIGH_ASK_F, 2.5);
+
+    assert_relative_eq!(msg.spread, LOW_ASK_F - HIGH_BID_F);
+    assert_eq!(asks[0].price_exact, LOW_ASK);
+    assert_eq!(asks[0].amount_exact, "2.56878000");
+
+    let bybit_info = msg.exchanges.iter().find(|i| i.exchange == "bybit");
+    let bybit_info = bybit_info.expect("bybit info");
+    assert!(bybit_info.sequence > 100, "{bybit_info:?}");
+    assert!(bybit_info.event_time_us > 0, "{bybit_info:?}");
+
+    // change the bybit book with deltas
+    bybit.set_orders(OrderBook {
+        bids: vec![[MID_BID, "0.70000000"].into()], // changed, removed LOW_BID
+        asks: vec![
+            [LOW_ASK, "2.56878000"].into(),
+            [HIGH_ASK, "10.10000000"].into(),
+        ],
+    });
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[1].amount == 0.7 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_level_eq!(msg.bids[1], "bybit", MID_BID_F, 0.7);
+    assert_level_eq!(msg.bids[2], "binance", LOW_BID_F, 10.5);
+    assert_eq!(msg.bids.len(), 3);
+
+    // a service restart resets the update id, later deltas must still apply
+    bybit.restart();
+    let a = Instant::now();
+    while bybit.restart_pending() {
+        assert!(a.elapsed() < TEST_WAIT, "restart not sent");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    bybit.set_orders(OrderBook {
+        bids: vec![[MID_BID, "0.80000000"].into()], // changed
+        asks: vec![
+            [LOW_ASK, "2.56878000"].into(),
+            [HIGH_ASK, "10.10000000"].into(),
+        ],
+    });
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[1].amount == 0.8 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT, "delta after restart not applied");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_level_eq!(msg.bids[1], "bybit", MID_BID_F, 0.8);
+    let bybit_info = msg.exchanges.iter().find(|i| i.exchange == "bybit");
+    assert!(bybit_info.expect("bybit info").sequence < 100);
+
+    // pings are answered, so the connection stays open
+    let a = Instant::now();
+    while bybit.pings() < 3 {
+        assert!(a.elapsed() < TEST_WAIT, "pings not sent");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(bybit.connections(), 1);
+}
@@ -0,0 +1,98 @@
+use crate::util::{coinbase::MockCoinbase, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the coinbase level2 channel local book.
+///
+/// Asserts the local book is built from the snapshot & updates, and is
+/// resubscribed after a dropped update is detected by the book crossing.
+#[tokio::test]
+async fn coinbase_level2() {
+    let coinbase = MockCoinbase::start();
+    coinbase.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    env::set_var("EXCHANGES", "coinbase");
+    env::set_var("COINBASE_URL", coinbase.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "coinbase", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "coinbase", 0.071389, 10.5);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "coinbase", 0.071438, 14.56878);
+    assert_level_eq!(msg.asks[1], "coinbase", 0.0715, 2.5);
+    assert_eq!(coinbase.subscriptions(), 1);
+
+    // lose the update removing the top ask
+    coinbase.drop_next_update();
+    coinbase.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![["0.07150000", "2.50000000"].into()], // removed 0.07143800
+    });
+    let a = Instant::now();
+    while coinbase.drop_pending() {
+        assert!(a.elapsed() < TEST_WAIT, "update not dropped");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // a new top bid above the missed removal crosses the local book
+    coinbase.set_orders(OrderBook {
+        bids: vec![
+            ["0.07145000", "1.00000000"].into(), // new
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![["0.07150000", "2.50000000"].into()],
+    });
+
+    // await the resubscribed book
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[0].price == 0.07145 && next.asks.len() == 1 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 3);
+    assert_level_eq!(msg.bids[0], "coinbase", 0.07145, 1.0);
+    assert_eq!(msg.asks.len(), 1);
+    assert_level_eq!(msg.asks[0], "coinbase", 0.0715, 2.5);
+    assert_eq!(
+        coinbase.subscriptions(),
+        2,
+        "dropped update did not resubscribe"
+    );
+    assert_eq!(coinbase.connections(), 1);
+}
@@ -1,22 +1,13 @@
 use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
 use approx::assert_relative_eq;
-use merged_order_book_protos::orderbook_aggregator_client::OrderbookAggregatorClient;
 use std::{
     env,
     time::{Duration, Instant},
 };
 
+#[macro_use]
 mod util;
 
-macro_rules! assert_level_eq {
-    ($lvl:expr, $name:expr, $price:expr, $amount:expr) => {{
-        let lvl = &$lvl;
-        assert_eq!(lvl.exchange, $name);
-        assert_relative_eq!(lvl.price, $price);
-        assert_relative_eq!(lvl.amount, $amount);
-    }};
-}
-
 /// Scenario test for the grpc service.
 ///
 /// Asserts that binance & bitstamp order book streams are listened
@@ -65,27 +56,13 @@ async fn grpc() {
     // configure & start grpc server
     env::set_var("BINANCE_URL", binance.url());
     env::set_var("BITSTAMP_URL", bitstamp.url());
-    let grpc_port = util::random_open_port().await;
-    env::set_var("GRPC_PORT", grpc_port.to_string());
-    tokio::spawn(async {
-        if let Err(err) = merged_order_book::start().await {
-            eprintln!("{err}");
-        }
-    });
-
-    // await a grpc connection
-    let a = Instant::now();
-    let mut client = loop {
-        let c = OrderbookAggregatorClient::connect(format!("http://localhost:{grpc_port}")).await;
-        if let Ok(client) = c {
-            break client;
-        }
-        assert!(a.elapsed() < TEST_WAIT);
-        tokio::time::sleep(Duration::from_millis(10)).await;
-    };
+    let mut client = util::start_server().await;
 
     let mut stream = client
-        .book_summary(merged_order_book_protos::Empty {})
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            symbol: "ETH/BTC".into(),
+            ..<_>::default()
+        })
         .await
         .expect("book_summary")
         .into_inner();
@@ -121,6 +98,115 @@ async fn grpc() {
 
     assert_relative_eq!(msg.spread, LOW_ASK_F - HIGH_BID_F);
 
+    // exact decimal values
+    assert_eq!(bids[0].price_exact, HIGH_BID);
+    assert_eq!(bids[0].amount_exact, "23.30750000");
+    assert_eq!(asks[0].price_exact, LOW_ASK);
+    assert_eq!(asks[0].amount_exact, "2.56878000");
+    assert_eq!(msg.spread_exact, "0.00003577");
+
+    // timestamps & sequencing
+    assert!(msg.sequence > 0);
+    assert!(msg.merge_time_us > 0);
+    assert_eq!(msg.exchanges.len(), 2);
+    for info in &msg.exchanges {
+        assert!(info.receive_time_us > 0, "{info:?}");
+        assert!(
+            info.event_time_us > 0 || info.exchange == "binance",
+            "{info:?}"
+        );
+    }
+    let binance_info = msg.exchanges.iter().find(|i| i.exchange == "binance");
+    assert!(binance_info.expect("binance info").sequence > 0);
+    let next = stream.message().await.unwrap().expect("stream closed");
+    assert!(next.sequence > msg.sequence);
+
+    // request an aggregated view, combining same price levels
+    let mut agg_stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            aggregate: true,
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let a = Instant::now();
+    let agg = loop {
+        let next = agg_stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == msg.bids.len() {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{agg:#?}");
+
+    let bids = &agg.aggregated_bids;
+    assert_eq!(bids.len(), 3);
+    assert_relative_eq!(bids[0].price, HIGH_BID_F);
+    assert_relative_eq!(bids[0].amount, 23.3075);
+    assert_relative_eq!(bids[1].price, MID_BID_F);
+    assert_relative_eq!(bids[1].amount, 0.6);
+    assert_eq!(bids[2].price_exact, LOW_BID);
+    assert_eq!(bids[2].amount_exact, "11.70000000");
+    assert_level_eq!(bids[2].levels[0], "binance", LOW_BID_F, 10.5);
+    assert_level_eq!(bids[2].levels[1], "bitstamp", LOW_BID_F, 1.2);
+
+    let asks = &agg.aggregated_asks;
+    assert_eq!(asks.len(), 3);
+    assert_relative_eq!(asks[0].price, LOW_ASK_F);
+    assert_relative_eq!(asks[1].price, MID_ASK_F);
+    assert_eq!(asks[2].price_exact, HIGH_ASK);
+    assert_eq!(asks[2].amount_exact, "12.60000000");
+    assert_level_eq!(asks[2].levels[0], "bitstamp", HIGH_ASK_F, 10.1);
+    assert_level_eq!(asks[2].levels[1], "binance", HIGH_ASK_F, 2.5);
+
+    // non-aggregated levels are unchanged
+    assert_eq!(agg.bids, msg.bids);
+    assert_eq!(agg.asks, msg.asks);
+
+    // request price buckets of 0.0001
+    let mut grouped_stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            tick_size: "0.0001".into(),
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let a = Instant::now();
+    let grouped = loop {
+        let next = grouped_stream
+            .message()
+            .await
+            .unwrap()
+            .expect("stream closed");
+        if next.bids.len() == msg.bids.len() {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{grouped:#?}");
+
+    // bids rounded down
+    let bids = &grouped.aggregated_bids;
+    assert_eq!(bids.len(), 2);
+    assert_eq!(bids[0].price_exact, "0.0714");
+    assert_eq!(bids[0].amount_exact, "23.30750000");
+    assert_eq!(bids[1].price_exact, "0.0713");
+    assert_eq!(bids[1].amount_exact, "12.30000000");
+    assert_level_eq!(bids[1].levels[0], "bitstamp", 0.0713, 1.8);
+    assert_level_eq!(bids[1].levels[1], "binance", 0.0713, 10.5);
+
+    // asks rounded up
+    let asks = &grouped.aggregated_asks;
+    assert_eq!(asks.len(), 1);
+    assert_eq!(asks[0].price_exact, "0.0715");
+    assert_eq!(asks[0].amount_exact, "29.73756000");
+    assert_level_eq!(asks[0].levels[0], "bitstamp", 0.0715, 12.66878);
+    assert_level_eq!(asks[0].levels[1], "binance", 0.0715, 17.06878);
+
     // bitstamp updates with a new lower ask
     const LOWER_ASK: &str = "0.07143300";
     const LOWER_ASK_F: f64 = 0.071433;
@@ -142,7 +228,7 @@ async fn grpc() {
         let next = stream.message().await.unwrap();
         let next = next.expect("stream closed");
 
-        if next != msg {
+        if (&next.bids, &next.asks) != (&msg.bids, &msg.asks) {
             break next;
         }
         assert!(a.elapsed() < TEST_WAIT);
@@ -186,7 +272,7 @@ async fn grpc() {
         let next = stream.message().await.unwrap();
         let next = next.expect("stream closed");
 
-        if next != msg {
+        if (&next.bids, &next.asks) != (&msg.bids, &msg.asks) {
             break next;
         }
         assert!(a.elapsed() < TEST_WAIT);
@@ -208,4 +294,65 @@ async fn grpc() {
     assert_level_eq!(asks[3], "binance", HIGH_ASK_F, 2.5);
 
     assert_relative_eq!(msg.spread, LOWER_ASK_F - HIGHER_BID_F);
+
+    // request a top 1 of only bitstamp levels
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            depth: 1,
+            exchanges: vec!["bitstamp".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 1);
+    assert_level_eq!(msg.bids[0], "bitstamp", MID_BID_F, 0.6);
+    assert_eq!(msg.asks.len(), 1);
+    assert_level_eq!(msg.asks[0], "bitstamp", LOWER_ASK_F, 10.1);
+    assert_relative_eq!(msg.spread, LOWER_ASK_F - MID_BID_F);
+
+    // unknown exchanges are rejected
+    let err = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["foo".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect_err("unknown exchange");
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+    // tick sizes small enough to overflow price buckets are rejected
+    let err = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            tick_size: "0.0000000000000000000000000001".into(),
+            ..<_>::default()
+        })
+        .await
+        .expect_err("tiny tick_size");
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+    // the smallest tick size groups without overflow
+    let mut grouped_stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            tick_size: "0.000000000001".into(),
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let grouped = grouped_stream
+        .message()
+        .await
+        .unwrap()
+        .expect("stream closed");
+    assert_eq!(grouped.aggregated_bids.len(), grouped.bids.len());
+    assert_eq!(grouped.aggregated_bids[0].price, grouped.bids[0].price);
+
+    // other streams continue
+    stream.message().await.unwrap().expect("stream closed");
 }
@@ -0,0 +1,97 @@
+use crate::util::{kraken::MockKraken, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the kraken book channel local book.
+///
+/// Asserts the local book is built from the snapshot & updates, verifying checksums
+/// of values sent without trailing zeros using the instrument precisions,
+/// and is resubscribed after an update checksum mismatch.
+#[tokio::test]
+async fn kraken_checksum() {
+    let kraken = MockKraken::start();
+    kraken.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    env::set_var("EXCHANGES", "kraken");
+    env::set_var("KRAKEN_URL", kraken.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "kraken", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "kraken", 0.071389, 10.5);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "kraken", 0.071438, 14.56878);
+    assert_level_eq!(msg.asks[1], "kraken", 0.0715, 2.5);
+    assert_eq!(msg.exchanges.len(), 1);
+
+    // updates with trimmed values, e.g. `2.5`, pass the checksum
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(kraken.subscriptions(), 1, "unexpected resubscribe");
+
+    // change the book, with a bad checksum
+    kraken.corrupt_next_checksum();
+    kraken.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(), // new
+            ["0.07140100", "23.30750000"].into(),
+        ], // removed 0.07138900
+        asks: vec![
+            ["0.07143800", "4.00000000"].into(), // changed
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    // await the resubscribed book
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[0].price == 0.07143 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "kraken", 0.07143, 10.5);
+    assert_level_eq!(msg.bids[1], "kraken", 0.071401, 23.3075);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "kraken", 0.071438, 4.0);
+    assert_level_eq!(msg.asks[1], "kraken", 0.0715, 2.5);
+    assert_eq!(
+        kraken.subscriptions(),
+        2,
+        "checksum mismatch did not resubscribe"
+    );
+    assert_eq!(kraken.connections(), 1);
+
+    // updates after the resubscribe snapshot have an event time
+    let msg = stream.message().await.unwrap().expect("stream closed");
+    assert!(msg.exchanges[0].event_time_us > 0, "{msg:#?}");
+}
@@ -0,0 +1,112 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for lazy exchange subscriptions.
+///
+/// Asserts exchanges are connected on the first request, kept during the grace
+/// period & disconnected after the last request ends, removing their last message age metrics.
+#[tokio::test]
+async fn lazy_subscriptions() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("LAZY_SUBSCRIPTIONS", "true");
+    env::set_var("SUBSCRIPTION_GRACE_MS", "500");
+    let mut client = util::start_server().await;
+
+    // not connected without requests
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(binance.connections(), 0);
+    assert_eq!(bitstamp.connections(), 0);
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    // await a message with both exchanges inside
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 2 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "bitstamp", 0.07138988, 0.6);
+    assert_eq!(binance.connections(), 1);
+    assert_eq!(bitstamp.connections(), 1);
+    let age = r#"exchange_last_message_age_seconds{exchange="bitstamp",symbol="eth/btc"}"#;
+    assert!(metrics().await.contains(age), "missing {age}");
+
+    // a new request within the grace period reuses the connections
+    drop(stream);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["bitstamp".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let msg = stream.message().await.unwrap().expect("stream closed");
+    assert_level_eq!(msg.bids[0], "bitstamp", 0.07138988, 0.6);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(binance.open_connections(), 1);
+    assert_eq!(bitstamp.open_connections(), 1);
+
+    // disconnected after the grace period without requests
+    drop(stream);
+    let a = Instant::now();
+    while binance.open_connections() > 0 || bitstamp.open_connections() > 0 {
+        assert!(a.elapsed() < TEST_WAIT, "exchanges not disconnected");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(binance.connections(), 1);
+    assert_eq!(bitstamp.connections(), 1);
+    assert!(!metrics().await.contains(age), "{age} not removed");
+
+    // re-connected on the next request
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["binance".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let msg = stream.message().await.unwrap().expect("stream closed");
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+    assert_eq!(binance.connections(), 2);
+}
+
+/// Fetches the prometheus metrics text.
+async fn metrics() -> String {
+    let port = env::var("METRICS_PORT").unwrap();
+    reqwest::get(format!("http://localhost:{port}/metrics"))
+        .await
+        .expect("metrics")
+        .text()
+        .await
+        .unwrap()
+}
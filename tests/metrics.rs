@@ -0,0 +1,149 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+mod util;
+
+/// Scenario test for the prometheus `/metrics` endpoint.
+#[tokio::test]
+async fn metrics() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            latency: true,
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    let latency = msg.latency.expect("latency");
+    assert!(["binance", "bitstamp"].contains(&latency.exchange.as_str()));
+    assert_eq!(
+        latency.total_us,
+        latency.receive_us + latency.merge_us + latency.send_us
+    );
+    assert!(latency.total_us < 1_000_000, "{latency:?}");
+
+    let metrics_url = format!(
+        "http://localhost:{}/metrics",
+        env::var("METRICS_PORT").unwrap()
+    );
+    let metrics = reqwest::get(&metrics_url)
+        .await
+        .expect("metrics")
+        .text()
+        .await
+        .unwrap();
+
+    eprintln!("{metrics}");
+
+    for exchange in ["binance", "bitstamp"] {
+        let labels = format!(r#"{{exchange="{exchange}",symbol="eth/btc"}}"#);
+        let value = |name: &str| -> f64 {
+            let prefix = format!("{name}{labels} ");
+            let line = metrics
+                .lines()
+                .find(|l| l.starts_with(&prefix))
+                .unwrap_or_else(|| panic!("missing {prefix}"));
+            line[prefix.len()..].parse().unwrap()
+        };
+        assert!(value("exchange_messages_total") > 0.0);
+        assert_eq!(value("exchange_invalid_messages_total"), 0.0);
+        assert_eq!(value("exchange_reconnects_total"), 0.0);
+        assert!(value("exchange_last_message_age_seconds") < 1.0);
+    }
+    for histogram in [
+        "merge_latency_seconds",
+        "grpc_send_latency_seconds",
+        "end_to_end_latency_seconds",
+    ] {
+        assert!(
+            metrics.contains(&format!("\n{histogram}_count{{exchange=")),
+            "missing {histogram}"
+        );
+    }
+    assert!(metrics.contains("\nexchange_latency_seconds_bucket{exchange=\"bitstamp\""));
+    assert!(metrics.contains("\ngrpc_streams 1\n"));
+    assert!(!metrics.contains("\nmerged_summaries_total 0\n"));
+    assert!(metrics.contains("\ngrpc_lagged_summaries_total "));
+    assert!(metrics.contains("\nsummary_mergers 1\n"));
+
+    // a request with other parameters has its own merger, removed when the request ends
+    let mut depth_stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            depth: 1,
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    depth_stream
+        .message()
+        .await
+        .unwrap()
+        .expect("stream closed");
+    assert_eq!(gauge(&metrics_url, "summary_mergers").await, 2);
+
+    // merge latency is observed once per exchange update, not per merger
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let metrics = reqwest::get(&metrics_url)
+        .await
+        .expect("metrics")
+        .text()
+        .await
+        .unwrap();
+    let value = |series: &str| -> f64 {
+        let prefix = format!("{series} ");
+        let line = metrics
+            .lines()
+            .find(|l| l.starts_with(&prefix))
+            .unwrap_or_else(|| panic!("missing {prefix}"));
+        line[prefix.len()..].parse().unwrap()
+    };
+    let merged = value(r#"merge_latency_seconds_count{exchange="bitstamp"}"#);
+    let received = value(r#"exchange_messages_total{exchange="bitstamp",symbol="eth/btc"}"#);
+    assert!(merged <= received, "{merged} merges of {received} messages");
+
+    drop(depth_stream);
+    let a = Instant::now();
+    while gauge(&metrics_url, "summary_mergers").await != 1 {
+        assert!(a.elapsed() < TEST_WAIT, "merger not removed");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(gauge(&metrics_url, "grpc_streams").await, 1);
+}
+
+/// Fetches the current value of a gauge without labels.
+async fn gauge(metrics_url: &str, name: &str) -> i64 {
+    let metrics = reqwest::get(metrics_url)
+        .await
+        .expect("metrics")
+        .text()
+        .await
+        .unwrap();
+    let prefix = format!("{name} ");
+    let line = metrics
+        .lines()
+        .find(|l| l.starts_with(&prefix))
+        .unwrap_or_else(|| panic!("missing {name}"));
+    line[prefix.len()..].parse().unwrap()
+}
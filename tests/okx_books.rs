@@ -0,0 +1,116 @@
+use crate::util::{okx::MockOkx, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the okx books channel local book.
+///
+/// Asserts the local book is built from the snapshot & updates,
+/// and is resubscribed after a `seqId` gap & after a checksum mismatch.
+#[tokio::test]
+async fn okx_books() {
+    let okx = MockOkx::start();
+    okx.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    env::set_var("EXCHANGES", "okx");
+    env::set_var("OKX_URL", okx.url());
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "okx", 0.071401, 23.3075);
+    assert_level_eq!(msg.bids[1], "okx", 0.071389, 10.5);
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "okx", 0.071438, 14.56878);
+    assert_level_eq!(msg.asks[1], "okx", 0.0715, 2.5);
+    assert!(msg.exchanges[0].sequence > 1000);
+    assert_eq!(okx.subscriptions(), 1);
+
+    // change the book, but drop the update causing a gap
+    okx.drop_next_update();
+    okx.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(), // new
+            ["0.07140100", "23.30750000"].into(),
+        ], // removed 0.07138900
+        asks: vec![
+            ["0.07143800", "14.56878000"].into(),
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    // await the resubscribed book
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids[0].price == 0.07143 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 2);
+    assert_level_eq!(msg.bids[0], "okx", 0.07143, 10.5);
+    assert_level_eq!(msg.bids[1], "okx", 0.071401, 23.3075);
+    assert_eq!(okx.subscriptions(), 2, "seqId gap did not resubscribe");
+
+    // change the book, with a bad checksum
+    okx.corrupt_next_checksum();
+    okx.set_orders(OrderBook {
+        bids: vec![
+            ["0.07143000", "10.50000000"].into(),
+            ["0.07140100", "23.30750000"].into(),
+        ],
+        asks: vec![
+            ["0.07143800", "4.00000000"].into(), // changed
+            ["0.07150000", "2.50000000"].into(),
+        ],
+    });
+
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.asks[0].amount == 4.0 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.asks.len(), 2);
+    assert_level_eq!(msg.asks[0], "okx", 0.071438, 4.0);
+    assert_level_eq!(msg.asks[1], "okx", 0.0715, 2.5);
+    assert_eq!(
+        okx.subscriptions(),
+        3,
+        "checksum mismatch did not resubscribe"
+    );
+    assert_eq!(okx.connections(), 1);
+}
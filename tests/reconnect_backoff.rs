@@ -0,0 +1,76 @@
+use crate::util::{binance::MockBinance, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the exchange re-connect backoff.
+///
+/// Asserts failed connections are retried after doubling, capped & jittered delays,
+/// and the exchange is given up on after `RECONNECT_MAX_ATTEMPTS`.
+#[tokio::test]
+async fn reconnect_backoff() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    // accepts tcp connections & immediately closes them, failing the websocket handshake
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let failing_port = listener.local_addr().unwrap().port();
+    let attempts = Arc::<Mutex<Vec<Instant>>>::default();
+    tokio::spawn({
+        let attempts = Arc::clone(&attempts);
+        async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                attempts.lock().unwrap().push(Instant::now());
+                drop(socket);
+            }
+        }
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", format!("ws://localhost:{failing_port}"));
+    env::set_var("BITSTAMP_RECONNECT_DELAY_MS", "200");
+    env::set_var("BITSTAMP_RECONNECT_MAX_DELAY_MS", "300");
+    env::set_var("BITSTAMP_RECONNECT_MAX_ATTEMPTS", "3");
+    env::set_var("STARTUP_POLICY", "any");
+    let mut client = util::start_server().await;
+
+    // the initial attempt & 3 re-connects
+    let a = Instant::now();
+    while attempts.lock().unwrap().len() < 4 {
+        assert!(a.elapsed() < TEST_WAIT, "bitstamp not re-connected");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // given up, no further attempts
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let attempts = attempts.lock().unwrap().clone();
+    assert_eq!(attempts.len(), 4, "bitstamp not given up");
+
+    // 50-100% of 200ms, 400ms capped to 300ms & 300ms
+    let gaps: Vec<_> = attempts.windows(2).map(|w| w[1] - w[0]).collect();
+    eprintln!("{gaps:?}");
+    let ms = Duration::from_millis;
+    assert!(gaps[0] >= ms(100) && gaps[0] < ms(300), "{gaps:?}");
+    assert!(gaps[1] >= ms(150) && gaps[1] < ms(400), "{gaps:?}");
+    assert!(gaps[2] >= ms(150) && gaps[2] < ms(400), "{gaps:?}");
+
+    // other exchanges are unaffected
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let msg = stream.message().await.unwrap().expect("stream closed");
+    assert_eq!(msg.bids.len(), 1);
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+}
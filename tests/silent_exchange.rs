@@ -0,0 +1,65 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for the no data watchdog.
+///
+/// Asserts an exchange connection that stays open but stops sending data
+/// is re-connected & resumes providing levels.
+#[tokio::test]
+async fn silent_exchange() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("BITSTAMP_NO_DATA_TIMEOUT_MS", "500");
+    let mut client = util::start_server().await;
+
+    assert_eq!(bitstamp.connections(), 1);
+
+    // stop sending data, without closing the connection
+    bitstamp.set_paused(true);
+
+    let a = Instant::now();
+    while bitstamp.connections() < 2 {
+        assert!(a.elapsed() < TEST_WAIT, "bitstamp not re-connected");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07139000", "1.20000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+    bitstamp.set_paused(false);
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            exchanges: vec!["bitstamp".into()],
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 1);
+    assert_level_eq!(msg.bids[0], "bitstamp", 0.07139, 1.2);
+}
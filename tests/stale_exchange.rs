@@ -0,0 +1,85 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{env, time::Instant};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for stale exchange eviction.
+///
+/// Asserts levels of an exchange that stops sending are excluded from the merge
+/// & flagged as stale, then restored when fresh data arrives.
+#[tokio::test]
+async fn stale_exchange() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![["0.07138988", "0.60000000"].into()],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    env::set_var("BITSTAMP_STALE_TIMEOUT_MS", "500");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    // await a message with both exchanges inside
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 2 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+    assert!(msg.exchanges.iter().all(|info| !info.stale));
+
+    bitstamp.set_paused(true);
+
+    // await bitstamp eviction
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 1 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+    assert_eq!(msg.asks.len(), 1);
+    assert_level_eq!(msg.asks[0], "binance", 0.071438, 14.56878);
+    let stale: Vec<_> = msg.exchanges.iter().filter(|i| i.stale).collect();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].exchange, "bitstamp");
+
+    bitstamp.set_paused(false);
+
+    // await bitstamp restoration
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 2 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_level_eq!(msg.bids[1], "bitstamp", 0.07138988, 0.6);
+    assert_level_eq!(msg.asks[0], "bitstamp", 0.07143677, 2.56878);
+    assert!(msg.exchanges.iter().all(|info| !info.stale));
+}
@@ -0,0 +1,69 @@
+use crate::util::{binance::MockBinance, OrderBook, TEST_WAIT};
+use std::{
+    env,
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for connecting to an exchange that never completes the websocket handshake.
+///
+/// Asserts the connection attempt times out after the connect timeout & is retried,
+/// even with the no data watchdog disabled, while other exchanges are served.
+#[tokio::test]
+async fn stalled_connect() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![["0.07140100", "23.30750000"].into()],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    // accepts tcp connections, but never responds
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let stalled_port = listener.local_addr().unwrap().port();
+    let accepted = Arc::<AtomicU64>::default();
+    tokio::spawn({
+        let accepted = Arc::clone(&accepted);
+        async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, atomic::Ordering::SeqCst);
+                sockets.push(socket);
+            }
+        }
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", format!("ws://localhost:{stalled_port}"));
+    env::set_var("BITSTAMP_CONNECT_TIMEOUT_MS", "500");
+    env::set_var("BITSTAMP_NO_DATA_TIMEOUT_MS", "0");
+    env::set_var("BITSTAMP_RECONNECT_DELAY_MS", "100");
+    env::set_var("STARTUP_POLICY", "any");
+    let mut client = util::start_server().await;
+
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest::default())
+        .await
+        .expect("book_summary")
+        .into_inner();
+
+    let msg = stream.message().await.unwrap().expect("stream closed");
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 1);
+    assert_level_eq!(msg.bids[0], "binance", 0.071401, 23.3075);
+
+    let a = Instant::now();
+    while accepted.load(atomic::Ordering::SeqCst) < 3 {
+        assert!(a.elapsed() < TEST_WAIT, "stalled connect not retried");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
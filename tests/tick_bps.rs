@@ -0,0 +1,95 @@
+use crate::util::{binance::MockBinance, bitstamp::MockBitstamp, OrderBook, TEST_WAIT};
+use std::{env, time::Instant};
+
+#[macro_use]
+mod util;
+
+/// Scenario test for `tick_bps` price buckets.
+///
+/// Asserts aggregated levels are grouped by basis points of the mid price,
+/// keeping the last bucket size while the mid price is unavailable.
+#[tokio::test]
+async fn tick_bps() {
+    let binance = MockBinance::start();
+    binance.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![["0.07143800", "14.56878000"].into()],
+    });
+
+    let bitstamp = MockBitstamp::start();
+    bitstamp.set_orders(OrderBook {
+        bids: vec![
+            ["0.07138988", "0.60000000"].into(),
+            ["0.07138900", "1.20000000"].into(),
+        ],
+        asks: vec![["0.07143677", "2.56878000"].into()],
+    });
+
+    env::set_var("BINANCE_URL", binance.url());
+    env::set_var("BITSTAMP_URL", bitstamp.url());
+    let mut client = util::start_server().await;
+
+    // mid ~0.07142, 10bps rounded down to 0.00007 buckets
+    let mut stream = client
+        .book_summary(merged_order_book_protos::BookSummaryRequest {
+            tick_bps: 10,
+            ..<_>::default()
+        })
+        .await
+        .expect("book_summary")
+        .into_inner();
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.bids.len() == 4 {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{msg:#?}");
+
+    let bids = &msg.aggregated_bids;
+    assert_eq!(bids.len(), 2);
+    assert_eq!(bids[0].price_exact, "0.071400");
+    assert_eq!(bids[0].amount_exact, "23.30750000");
+    assert_eq!(bids[1].price_exact, "0.071330");
+    assert_eq!(bids[1].amount_exact, "12.30000000");
+
+    // without asks there is no mid price, the last bucket size is kept
+    binance.set_orders(OrderBook {
+        bids: vec![
+            ["0.07140100", "23.30750000"].into(),
+            ["0.07138900", "10.50000000"].into(),
+        ],
+        asks: vec![],
+    });
+    bitstamp.set_orders(OrderBook {
+        bids: vec![
+            ["0.07138988", "0.60000000"].into(),
+            ["0.07138900", "1.20000000"].into(),
+        ],
+        asks: vec![],
+    });
+    let a = Instant::now();
+    let msg = loop {
+        let next = stream.message().await.unwrap().expect("stream closed");
+        if next.asks.is_empty() {
+            break next;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+    };
+
+    eprintln!("{msg:#?}");
+
+    assert_eq!(msg.bids.len(), 4);
+    let bids = &msg.aggregated_bids;
+    assert_eq!(bids.len(), 2);
+    assert_eq!(bids[0].price_exact, "0.071400");
+    assert_eq!(bids[1].price_exact, "0.071330");
+    assert_eq!(bids[1].amount_exact, "12.30000000");
+    assert!(msg.aggregated_asks.is_empty());
+}
@@ -4,18 +4,20 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
         State,
     },
+    http::StatusCode,
     response::IntoResponse,
     routing::get,
-    Router,
+    Json, Router,
 };
 use std::{
     net::SocketAddr,
     sync::{
-        atomic::{self, AtomicU64},
-        Arc, RwLock,
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, Mutex, RwLock,
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::broadcast;
 
 /// Localhost mock binance ws server. Sends messages every ~100ms.
 ///
@@ -37,19 +39,96 @@ use std::{
 ///     ]
 ///  }
 /// ```
+///
+/// Also serves the diff depth stream `/ws/ethbtc@depth@100ms` & REST `/api/v3/depth` snapshots.
+///
+/// # Example `/ws/ethbtc@depth@100ms` message
+/// ```json
+/// {
+///     "e": "depthUpdate",
+///     "E": 1674477880557,
+///     "s": "ETHBTC",
+///     "U": 157,
+///     "u": 160,
+///     "b": [
+///         [
+///             "0.07140100",
+///             "23.30750000"
+///         ],...
+///     ],
+///     "a": [
+///         [
+///             "0.07140200",
+///             "0.00000000"
+///         ],...
+///     ]
+/// }
+/// ```
 pub struct MockBinance {
     data: Arc<RwLock<OrderBook>>,
+    diff: Arc<DiffState>,
+    connections: Arc<Connections>,
     port: u16,
 }
 
+/// Websocket connection counters.
+#[derive(Default)]
+struct Connections {
+    total: AtomicU64,
+    open: AtomicU64,
+}
+
+impl Connections {
+    /// Counts the connection while `f` runs.
+    async fn track(&self, f: impl std::future::Future<Output = ()>) {
+        self.total.fetch_add(1, atomic::Ordering::SeqCst);
+        self.open.fetch_add(1, atomic::Ordering::SeqCst);
+        f.await;
+        self.open.fetch_sub(1, atomic::Ordering::SeqCst);
+        eprintln!("MockBinance connection closed");
+    }
+}
+
+/// Diff depth stream state.
+struct DiffState {
+    /// Book as of the last published update & its update id.
+    published: Mutex<(OrderBook, u64)>,
+    updates: broadcast::Sender<String>,
+    drop_next: AtomicBool,
+    snapshot_requests: AtomicU64,
+    /// Respond to snapshot requests with `429 Too Many Requests`.
+    fail_snapshots: AtomicBool,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    diff: Arc<DiffState>,
+    connections: Arc<Connections>,
+}
+
 impl MockBinance {
     pub fn start() -> Self {
-        let data = Arc::default();
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let diff = Arc::new(DiffState {
+            published: <_>::default(),
+            updates: broadcast::channel(16).0,
+            drop_next: <_>::default(),
+            snapshot_requests: <_>::default(),
+            fail_snapshots: <_>::default(),
+        });
+        let connections = Arc::<Connections>::default();
 
         let addr = SocketAddr::from(([127, 0, 0, 1], 0));
         let app = Router::new()
             .route("/ws/ethbtc@depth10@100ms", get(ws_handler))
-            .with_state(Arc::clone(&data));
+            .route("/ws/ethbtc@depth@100ms", get(diff_ws_handler))
+            .route("/api/v3/depth", get(snapshot_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                diff: Arc::clone(&diff),
+                connections: Arc::clone(&connections),
+            });
         let server = axum::Server::bind(&addr)
             .serve(app.into_make_service_with_connect_info::<SocketAddr>());
         let port = server.local_addr().port();
@@ -58,30 +137,93 @@ impl MockBinance {
             eprintln!("MockBinance listening on {}", server.local_addr());
             server.await.unwrap();
         });
+        tokio::spawn(publish_diffs(Arc::clone(&data), Arc::clone(&diff)));
 
-        Self { data, port }
+        Self {
+            data,
+            diff,
+            connections,
+            port,
+        }
     }
 
     pub fn url(&self) -> String {
         format!("ws://localhost:{}", self.port)
     }
 
+    pub fn rest_url(&self) -> String {
+        format!("http://localhost:{}", self.port)
+    }
+
     /// Update order book data. Will be sent on the next websocket update.
     pub fn set_orders(&self, book: OrderBook) {
         *self.data.write().unwrap() = book;
     }
+
+    /// Skip publishing the next diff depth update, causing a sequence gap.
+    pub fn drop_next_update(&self) {
+        self.diff.drop_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.total.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of currently open websocket connections.
+    pub fn open_connections(&self) -> u64 {
+        self.connections.open.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Respond to REST snapshot requests with `429 Too Many Requests` while `true`.
+    pub fn set_snapshots_failing(&self, failing: bool) {
+        self.diff
+            .fail_snapshots
+            .store(failing, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of REST snapshot requests received.
+    pub fn snapshot_requests(&self) -> u64 {
+        self.diff.snapshot_requests.load(atomic::Ordering::SeqCst)
+    }
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(data): State<Arc<RwLock<OrderBook>>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|ws| connect_ws(ws, data))
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(move |ws| async move {
+        shared
+            .connections
+            .track(connect_ws(ws, Arc::clone(&shared.data)))
+            .await
+    })
+}
+
+async fn diff_ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    let updates = shared.diff.updates.subscribe();
+    ws.on_upgrade(
+        move |ws| async move { shared.connections.track(connect_diff_ws(ws, updates)).await },
+    )
+}
+
+async fn snapshot_handler(State(shared): State<Shared>) -> impl IntoResponse {
+    shared
+        .diff
+        .snapshot_requests
+        .fetch_add(1, atomic::Ordering::SeqCst);
+    if shared.diff.fail_snapshots.load(atomic::Ordering::SeqCst) {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+    let published = shared.diff.published.lock().unwrap();
+    let (book, last_update_id) = &*published;
+    Ok(Json(serde_json::json!({
+        "lastUpdateId": last_update_id,
+        "bids": book.bids.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+        "asks": book.asks.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+    })))
 }
 
 /// Actual websocket statemachine (one will be spawned per connection)
 async fn connect_ws(mut ws: WebSocket, data: Arc<RwLock<OrderBook>>) {
-    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
 
     eprintln!("MockBinance publishing on new connection");
 
@@ -106,3 +248,43 @@ async fn connect_ws(mut ws: WebSocket, data: Arc<RwLock<OrderBook>>) {
         tokio::time::sleep(Duration::from_millis(100)).await;
     }
 }
+
+/// Diff depth websocket statemachine, forwards published updates.
+async fn connect_diff_ws(mut ws: WebSocket, mut updates: broadcast::Receiver<String>) {
+    eprintln!("MockBinance publishing diffs on new connection");
+
+    while let Ok(msg) = updates.recv().await {
+        if ws.send(Message::Text(msg)).await.is_err() {
+            return; // connection closed
+        }
+    }
+}
+
+/// Publishes a diff depth update every ~100ms.
+async fn publish_diffs(data: Arc<RwLock<OrderBook>>, diff: Arc<DiffState>) {
+    loop {
+        let msg = {
+            let data = data.read().unwrap();
+            let mut published = diff.published.lock().unwrap();
+            let (book, update_id) = &mut *published;
+            *update_id += 1;
+            let msg = serde_json::json!({
+                "e": "depthUpdate",
+                "E": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+                "s": "ETHBTC",
+                "U": update_id,
+                "u": update_id,
+                "b": data.bids_diff(book),
+                "a": data.asks_diff(book),
+            });
+            *book = data.clone();
+            msg
+        };
+
+        if !diff.drop_next.swap(false, atomic::Ordering::SeqCst) {
+            _ = diff.updates.send(serde_json::to_string(&msg).unwrap());
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
@@ -0,0 +1,281 @@
+use crate::util::{Order, OrderBook};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+/// Book channel id of subscriptions.
+const CHAN_ID: u64 = 17;
+
+/// Supported trading pair symbols, all publishing the same book.
+const SYMBOLS: &[&str] = &["tETHBTC", "tBTCUST", "tSOLUDC", "tDOGE:USD"];
+
+/// Localhost mock bitfinex v2 public ws server. Sends book updates every ~100ms.
+///
+/// Subscribe with message
+/// `{"event":"subscribe","channel":"book","symbol":"tETHBTC","prec":"P0","len":"25"}`,
+/// a snapshot is sent followed by an update per changed level (or the unchanged top bid),
+/// each batch followed by a checksum message. A heartbeat is sent every ~1s.
+///
+/// # Example messages
+/// ```json
+/// [17, [[0.071401, 2, 23.3075], [0.071438, 1, -14.56878],...]]
+/// [17, [0.071389, 0, 1]]
+/// [17, "cs", -855196043]
+/// [17, "hb"]
+/// ```
+///
+/// Subscriptions of symbols other than `tETHBTC`, `tBTCUST`, `tSOLUDC` & `tDOGE:USD` are rejected
+/// with an `error` event.
+pub struct MockBitfinex {
+    data: Arc<RwLock<OrderBook>>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+    symbols: Arc<Mutex<Vec<String>>>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+    symbols: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockBitfinex {
+    pub fn start() -> Self {
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let corrupt_next = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let subscriptions = Arc::<AtomicU64>::default();
+        let symbols = Arc::<Mutex<Vec<String>>>::default();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let app = Router::new()
+            .route("/ws/2", get(ws_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                corrupt_next: Arc::clone(&corrupt_next),
+                connections: Arc::clone(&connections),
+                subscriptions: Arc::clone(&subscriptions),
+                symbols: Arc::clone(&symbols),
+            });
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+
+        tokio::spawn(async move {
+            eprintln!("MockBitfinex listening on {}", server.local_addr());
+            server.await.unwrap();
+        });
+
+        Self {
+            data,
+            corrupt_next,
+            connections,
+            subscriptions,
+            symbols,
+            port,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://localhost:{}/ws/2", self.port)
+    }
+
+    /// Update order book data. Will be sent on the next websocket update.
+    pub fn set_orders(&self, book: OrderBook) {
+        *self.data.write().unwrap() = book;
+    }
+
+    /// Sends the next checksum message with an incorrect checksum.
+    pub fn corrupt_next_checksum(&self) {
+        self.corrupt_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of successful book subscriptions received.
+    pub fn subscriptions(&self) -> u64 {
+        self.subscriptions.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Symbols of all book subscriptions received, e.g. `tETHBTC`.
+    pub fn subscribed_symbols(&self) -> Vec<String> {
+        self.symbols.lock().unwrap().clone()
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn connect_ws(mut ws: WebSocket, shared: Shared) {
+    eprintln!("MockBitfinex new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+
+    let info = serde_json::json!({"event": "info", "version": 2, "platform": {"status": 1}});
+    if ws.send(Message::Text(info.to_string())).await.is_err() {
+        return;
+    }
+
+    // book as of the last published message, `None` until subscribed
+    let mut published: Option<OrderBook> = None;
+    let mut ticks = 0_u64;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => {
+                let json = match msg {
+                    Some(Ok(Message::Text(json))) => json,
+                    Some(Ok(_)) => continue,
+                    _ => break, // connection closed
+                };
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) else {
+                    continue;
+                };
+                let mut responses = vec![];
+                match json["event"].as_str() {
+                    Some("conf") => {
+                        responses.push(serde_json::json!({
+                            "event": "conf",
+                            "status": "OK",
+                            "flags": json["flags"],
+                        }));
+                    }
+                    Some("subscribe") if !SYMBOLS.contains(&symbol(&json)) => {
+                        shared.symbols.lock().unwrap().push(symbol(&json).into());
+                        responses.push(serde_json::json!({
+                            "event": "error",
+                            "msg": "symbol: invalid",
+                            "code": 10300,
+                        }));
+                    }
+                    Some("subscribe") => {
+                        shared.symbols.lock().unwrap().push(symbol(&json).into());
+                        shared.subscriptions.fetch_add(1, atomic::Ordering::SeqCst);
+                        let book = shared.data.read().unwrap().clone();
+                        responses.push(serde_json::json!({
+                            "event": "subscribed",
+                            "channel": "book",
+                            "chanId": CHAN_ID,
+                            "symbol": json["symbol"],
+                            "prec": json["prec"],
+                            "freq": json["freq"],
+                            "len": json["len"],
+                        }));
+                        let levels: Vec<_> = book
+                            .bids
+                            .iter()
+                            .map(|o| level(o.as_array(), 1.0))
+                            .chain(book.asks.iter().map(|o| level(o.as_array(), -1.0)))
+                            .collect();
+                        responses.push(serde_json::json!([CHAN_ID, levels]));
+                        responses.push(serde_json::json!([CHAN_ID, "cs", checksum(&book)]));
+                        published = Some(book);
+                    }
+                    _ => continue,
+                }
+                for response in responses {
+                    if ws.send(Message::Text(response.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = interval.tick() => {
+                let Some(prev) = &mut published else {
+                    continue;
+                };
+                let msgs = {
+                    let book = shared.data.read().unwrap();
+                    let mut msgs: Vec<_> = book
+                        .bids_diff(prev)
+                        .into_iter()
+                        .map(|l| serde_json::json!([CHAN_ID, level(l, 1.0)]))
+                        .chain(
+                            book.asks_diff(prev)
+                                .into_iter()
+                                .map(|l| serde_json::json!([CHAN_ID, level(l, -1.0)])),
+                        )
+                        .collect();
+                    // unchanged, re-send the top bid as if its order count changed
+                    if let (true, Some(bid)) = (msgs.is_empty(), book.bids.first()) {
+                        msgs.push(serde_json::json!([CHAN_ID, level(bid.as_array(), 1.0)]));
+                    }
+                    let corrupt = shared.corrupt_next.swap(false, atomic::Ordering::SeqCst);
+                    let checksum = if corrupt { 42 } else { checksum(&book) };
+                    msgs.push(serde_json::json!([CHAN_ID, "cs", checksum]));
+                    ticks += 1;
+                    if ticks.is_multiple_of(10) {
+                        msgs.push(serde_json::json!([CHAN_ID, "hb"]));
+                    }
+                    *prev = book.clone();
+                    msgs
+                };
+                for msg in msgs {
+                    if ws.send(Message::Text(msg.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    eprintln!("MockBitfinex connection closed");
+}
+
+/// Subscribe message symbol, e.g. `tETHBTC`.
+fn symbol(json: &serde_json::Value) -> &str {
+    json["symbol"].as_str().unwrap_or_default()
+}
+
+/// Returns a `[price, count, amount]` level, with a positive amount for bids & negative for asks.
+///
+/// Removed levels have a zero count & an amount of `1` for bids & `-1` for asks.
+fn level([price, amount]: [&str; 2], sign: f64) -> serde_json::Value {
+    let price: f64 = price.parse().unwrap();
+    let amount: f64 = amount.parse().unwrap();
+    match amount == 0.0 {
+        true => serde_json::json!([price, 0, sign]),
+        false => serde_json::json!([price, 1, amount * sign]),
+    }
+}
+
+/// Signed CRC32 of the top 25 bids & asks interleaved, `bid:amount:ask:-amount:...`.
+fn checksum(book: &OrderBook) -> i32 {
+    let number = |order: &Order, sign: f64| {
+        let price: f64 = order.price.parse().unwrap();
+        let amount: f64 = order.amount.parse().unwrap();
+        [price.to_string(), (amount * sign).to_string()]
+    };
+    let mut values = vec![];
+    for idx in 0..25 {
+        if let Some(bid) = book.bids.get(idx) {
+            values.extend(number(bid, 1.0));
+        }
+        if let Some(ask) = book.asks.get(idx) {
+            values.extend(number(ask, -1.0));
+        }
+    }
+    crc32fast::hash(values.join(":").as_bytes()) as i32
+}
@@ -6,13 +6,17 @@ use axum::{
     },
     response::IntoResponse,
     routing::get,
-    Router,
+    Json, Router,
 };
 use std::{
     net::SocketAddr,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, Mutex, RwLock,
+    },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::broadcast;
 
 /// Localhost mock bitstamp ws server. Sends messages every ~200ms.
 ///
@@ -41,19 +45,70 @@ use std::{
 ///     "event": "data"
 /// }
 /// ```
+///
+/// Also serves the `diff_order_book_ethbtc` channel, with the same message format
+/// but only changed levels, & REST `/api/v2/order_book/ethbtc/` snapshots.
+///
+/// Other channel subscriptions are rejected with a `bts:error` event,
+/// as are all subscriptions while rejecting, see [`MockBitstamp::set_rejecting`].
 pub struct MockBitstamp {
     data: Arc<RwLock<OrderBook>>,
+    diff: Arc<DiffState>,
+    paused: Arc<AtomicBool>,
+    rejecting: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    open_connections: Arc<AtomicU64>,
+    events: broadcast::Sender<String>,
     port: u16,
 }
 
+/// Diff order book channel state.
+struct DiffState {
+    /// Book as of the last published diff & its microtimestamp.
+    published: Mutex<(OrderBook, u128)>,
+    updates: broadcast::Sender<String>,
+    snapshot_requests: AtomicU64,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    diff: Arc<DiffState>,
+    paused: Arc<AtomicBool>,
+    rejecting: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    open_connections: Arc<AtomicU64>,
+    /// Non-data events sent to all connections.
+    events: broadcast::Sender<String>,
+}
+
 impl MockBitstamp {
     pub fn start() -> Self {
-        let data = Arc::default();
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let diff = Arc::new(DiffState {
+            published: <_>::default(),
+            updates: broadcast::channel(16).0,
+            snapshot_requests: <_>::default(),
+        });
+        let paused = Arc::<AtomicBool>::default();
+        let rejecting = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let open_connections = Arc::<AtomicU64>::default();
+        let events = broadcast::channel(16).0;
 
         let addr = SocketAddr::from(([127, 0, 0, 1], 0));
         let app = Router::new()
             .route("/", get(ws_handler))
-            .with_state(Arc::clone(&data));
+            .route("/api/v2/order_book/ethbtc/", get(snapshot_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                diff: Arc::clone(&diff),
+                paused: Arc::clone(&paused),
+                rejecting: Arc::clone(&rejecting),
+                connections: Arc::clone(&connections),
+                open_connections: Arc::clone(&open_connections),
+                events: events.clone(),
+            });
         let server = axum::Server::bind(&addr)
             .serve(app.into_make_service_with_connect_info::<SocketAddr>());
         let port = server.local_addr().port();
@@ -62,54 +117,158 @@ impl MockBitstamp {
             eprintln!("MockBitstamp listening on {}", server.local_addr());
             server.await.unwrap();
         });
+        tokio::spawn(publish_diffs(
+            Arc::clone(&data),
+            Arc::clone(&diff),
+            Arc::clone(&paused),
+        ));
 
-        Self { data, port }
+        Self {
+            data,
+            diff,
+            paused,
+            rejecting,
+            connections,
+            open_connections,
+            events,
+            port,
+        }
     }
 
     pub fn url(&self) -> String {
         format!("ws://localhost:{}", self.port)
     }
 
+    pub fn rest_url(&self) -> String {
+        format!("http://localhost:{}", self.port)
+    }
+
     /// Update order book data. Will be sent on the next websocket update.
     pub fn set_orders(&self, book: OrderBook) {
         *self.data.write().unwrap() = book;
     }
+
+    /// Stop or resume sending websocket messages, connections stay open.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, atomic::Ordering::SeqCst);
+    }
+
+    /// Reject or accept subscriptions of new connections.
+    pub fn set_rejecting(&self, rejecting: bool) {
+        self.rejecting.store(rejecting, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of currently open websocket connections.
+    pub fn open_connections(&self) -> u64 {
+        self.open_connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Sends a `bts:request_reconnect` event to all connections, which continue publishing.
+    pub fn request_reconnect(&self) {
+        _ = self
+            .events
+            .send(r#"{"event":"bts:request_reconnect","channel":"","data":""}"#.into());
+    }
+
+    /// Number of REST snapshot requests received.
+    pub fn snapshot_requests(&self) -> u64 {
+        self.diff.snapshot_requests.load(atomic::Ordering::SeqCst)
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(data): State<Arc<RwLock<OrderBook>>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|ws| connect_ws(ws, data))
+async fn snapshot_handler(State(shared): State<Shared>) -> impl IntoResponse {
+    shared
+        .diff
+        .snapshot_requests
+        .fetch_add(1, atomic::Ordering::SeqCst);
+    let published = shared.diff.published.lock().unwrap();
+    let (book, microtimestamp) = &*published;
+    Json(serde_json::json!({
+        "timestamp": (microtimestamp / 1_000_000).to_string(),
+        "microtimestamp": microtimestamp.to_string(),
+        "bids": book.bids.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+        "asks": book.asks.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+    }))
 }
 
 /// Actual websocket statemachine (one will be spawned per connection)
-async fn connect_ws(mut ws: WebSocket, data: Arc<RwLock<OrderBook>>) {
+async fn connect_ws(ws: WebSocket, shared: Shared) {
     eprintln!("MockBitstamp new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+    shared
+        .open_connections
+        .fetch_add(1, atomic::Ordering::SeqCst);
+    publish(ws, &shared).await;
+    shared
+        .open_connections
+        .fetch_sub(1, atomic::Ordering::SeqCst);
+    eprintln!("MockBitstamp connection closed");
+}
+
+/// Awaits a subscription & publishes until the connection closes.
+async fn publish(mut ws: WebSocket, shared: &Shared) {
+    let mut events = shared.events.subscribe();
 
     // await subscribe message
-    loop {
+    let channel = loop {
         if let Some(msg) = ws.recv().await {
             if let Ok(Message::Text(json)) = msg {
                 if let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) {
-                    if json["event"] == "bts:subscribe"
-                        && json["data"]["channel"] == "order_book_ethbtc"
-                    {
-                        break; // start publishing
+                    let channel = json["data"]["channel"].as_str().unwrap_or_default();
+                    if json["event"] != "bts:subscribe" {
+                        continue;
                     }
+                    let supported =
+                        channel == "order_book_ethbtc" || channel == "diff_order_book_ethbtc";
+                    if supported && !shared.rejecting.load(atomic::Ordering::SeqCst) {
+                        break channel.to_owned(); // start publishing
+                    }
+                    let error = serde_json::json!({
+                        "event": "bts:error",
+                        "channel": "",
+                        "data": {"code": null, "message": "Bad subscription string."},
+                    });
+                    _ = ws.send(Message::Text(error.to_string())).await;
                 }
             }
         } else {
             return; // connection closed
         }
+    };
+
+    if channel == "diff_order_book_ethbtc" {
+        eprintln!("MockBitstamp publishing diffs on new connection");
+        let mut updates = shared.diff.updates.subscribe();
+        while let Ok(msg) = updates.recv().await {
+            if ws.send(Message::Text(msg)).await.is_err() {
+                return; // connection closed
+            }
+            if !send_events(&mut ws, &mut events).await {
+                return;
+            }
+        }
+        return;
     }
 
     eprintln!("MockBitstamp publishing on new connection");
 
     loop {
+        if shared.paused.load(atomic::Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            continue;
+        }
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let msg = {
-            let data = data.read().unwrap();
+            let data = shared.data.read().unwrap();
             serde_json::json!({
                 "data": {
                     "timestamp": timestamp.as_secs().to_string(),
@@ -130,6 +289,57 @@ async fn connect_ws(mut ws: WebSocket, data: Arc<RwLock<OrderBook>>) {
             return; // connection closed
         }
 
+        if !send_events(&mut ws, &mut events).await {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Sends pending non-data events, returns `false` if the connection closed.
+async fn send_events(ws: &mut WebSocket, events: &mut broadcast::Receiver<String>) -> bool {
+    while let Ok(event) = events.try_recv() {
+        if ws.send(Message::Text(event)).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Publishes a diff order book message every ~200ms.
+async fn publish_diffs(
+    data: Arc<RwLock<OrderBook>>,
+    diff: Arc<DiffState>,
+    paused: Arc<AtomicBool>,
+) {
+    loop {
+        if paused.load(atomic::Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            continue;
+        }
+        let msg = {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            let data = data.read().unwrap();
+            let mut published = diff.published.lock().unwrap();
+            let (book, microtimestamp) = &mut *published;
+            *microtimestamp = timestamp.as_micros();
+            let msg = serde_json::json!({
+                "data": {
+                    "timestamp": timestamp.as_secs().to_string(),
+                    "microtimestamp": microtimestamp.to_string(),
+                    "bids": data.bids_diff(book),
+                    "asks": data.asks_diff(book),
+                },
+                "channel": "diff_order_book_ethbtc",
+                "event": "data",
+            });
+            *book = data.clone();
+            msg
+        };
+
+        _ = diff.updates.send(serde_json::to_string(&msg).unwrap());
+
         tokio::time::sleep(Duration::from_millis(200)).await;
     }
 }
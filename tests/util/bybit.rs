@@ -0,0 +1,240 @@
+use crate::util::OrderBook;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Localhost mock bybit v5 public spot ws server. Sends orderbook deltas every ~100ms.
+///
+/// Subscribe with message `{"op":"subscribe","args":["orderbook.50.ETHBTC"]}`,
+/// a snapshot is sent followed by deltas of changed levels.
+///
+/// # Example message
+/// ```json
+/// {
+///     "topic": "orderbook.50.ETHBTC",
+///     "type": "delta",
+///     "ts": 1674477880557,
+///     "data": {
+///         "s": "ETHBTC",
+///         "b": [["0.07140100", "23.30750000"],...],
+///         "a": [["0.07143800", "0"],...],
+///         "u": 18521288,
+///         "seq": 7961638724
+///     },
+///     "cts": 1674477880550
+/// }
+/// ```
+///
+/// Application level `{"op":"ping"}` messages are answered with a pong response.
+/// Other topic subscriptions are rejected with a `"success":false` response.
+pub struct MockBybit {
+    data: Arc<RwLock<OrderBook>>,
+    restart: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    pings: Arc<AtomicU64>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    restart: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    pings: Arc<AtomicU64>,
+}
+
+impl MockBybit {
+    pub fn start() -> Self {
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let restart = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let pings = Arc::<AtomicU64>::default();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let app = Router::new()
+            .route("/v5/public/spot", get(ws_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                restart: Arc::clone(&restart),
+                connections: Arc::clone(&connections),
+                pings: Arc::clone(&pings),
+            });
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+
+        tokio::spawn(async move {
+            eprintln!("MockBybit listening on {}", server.local_addr());
+            server.await.unwrap();
+        });
+
+        Self {
+            data,
+            restart,
+            connections,
+            pings,
+            port,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://localhost:{}/v5/public/spot", self.port)
+    }
+
+    /// Update order book data. Will be sent on the next websocket update.
+    pub fn set_orders(&self, book: OrderBook) {
+        *self.data.write().unwrap() = book;
+    }
+
+    /// Simulates a bybit service restart, resetting the update id `u` to 1 with a new snapshot.
+    pub fn restart(&self) {
+        self.restart.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Returns `true` if a restart is pending.
+    pub fn restart_pending(&self) -> bool {
+        self.restart.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of `{"op":"ping"}` messages received.
+    pub fn pings(&self) -> u64 {
+        self.pings.load(atomic::Ordering::SeqCst)
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn connect_ws(mut ws: WebSocket, shared: Shared) {
+    eprintln!("MockBybit new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+
+    // book as of the last published message, `None` until subscribed
+    let mut published: Option<OrderBook> = None;
+    let mut update_id = 100;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => {
+                let json = match msg {
+                    Some(Ok(Message::Text(json))) => json,
+                    Some(Ok(_)) => continue,
+                    _ => break, // connection closed
+                };
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) else {
+                    continue;
+                };
+                let mut responses = vec![];
+                match json["op"].as_str() {
+                    Some("ping") => {
+                        shared.pings.fetch_add(1, atomic::Ordering::SeqCst);
+                        responses.push(op_response("ping", true, "pong"));
+                    }
+                    Some("subscribe") if json["args"][0] != "orderbook.50.ETHBTC" => {
+                        let msg = format!("Invalid symbol :[{}]", json["args"][0]);
+                        responses.push(op_response("subscribe", false, &msg));
+                    }
+                    Some("subscribe") => {
+                        let book = shared.data.read().unwrap().clone();
+                        responses.push(op_response("subscribe", true, "subscribe"));
+                        update_id += 1;
+                        responses.push(orderbook_message("snapshot", &book, &book, update_id));
+                        published = Some(book);
+                    }
+                    _ => continue,
+                }
+                for response in responses {
+                    if ws.send(Message::Text(response.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = interval.tick() => {
+                let Some(prev) = &mut published else {
+                    continue;
+                };
+                let msg = {
+                    let book = shared.data.read().unwrap();
+                    let msg = match shared.restart.swap(false, atomic::Ordering::SeqCst) {
+                        true => {
+                            update_id = 1;
+                            orderbook_message("snapshot", &book, &book, update_id)
+                        }
+                        false => {
+                            update_id += 1;
+                            orderbook_message("delta", &book, prev, update_id)
+                        }
+                    };
+                    *prev = book.clone();
+                    msg
+                };
+                if ws.send(Message::Text(msg.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    eprintln!("MockBybit connection closed");
+}
+
+/// Operation response.
+fn op_response(op: &str, success: bool, ret_msg: &str) -> serde_json::Value {
+    serde_json::json!({
+        "success": success,
+        "ret_msg": ret_msg,
+        "conn_id": "mock",
+        "op": op,
+    })
+}
+
+/// Orderbook snapshot of all levels, or delta of the changes since `prev`.
+fn orderbook_message(
+    kind: &str,
+    book: &OrderBook,
+    prev: &OrderBook,
+    update_id: u64,
+) -> serde_json::Value {
+    let (bids, asks) = match kind {
+        "snapshot" => (
+            book.bids.iter().map(|o| o.as_array()).collect(),
+            book.asks.iter().map(|o| o.as_array()).collect(),
+        ),
+        _ => (book.bids_diff(prev), book.asks_diff(prev)),
+    };
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    serde_json::json!({
+        "topic": "orderbook.50.ETHBTC",
+        "type": kind,
+        "ts": ts.as_millis() as u64,
+        "data": {
+            "s": "ETHBTC",
+            "b": bids,
+            "a": asks,
+            "u": update_id,
+            "seq": update_id + 7_000_000,
+        },
+        "cts": ts.as_millis() as u64,
+    })
+}
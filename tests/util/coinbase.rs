@@ -0,0 +1,228 @@
+use crate::util::OrderBook;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Localhost mock coinbase exchange ws feed. Sends level2 updates every ~100ms.
+///
+/// Subscribe with message
+/// `{"type":"subscribe","product_ids":["ETH-BTC"],"channels":["level2_batch"]}`,
+/// a snapshot is sent followed by `l2update` messages of changed levels.
+///
+/// # Example messages
+/// ```json
+/// {
+///     "type": "snapshot",
+///     "product_id": "ETH-BTC",
+///     "bids": [["0.07140100", "23.30750000"],...],
+///     "asks": [["0.07143800", "14.56878000"],...]
+/// }
+/// ```
+/// ```json
+/// {
+///     "type": "l2update",
+///     "product_id": "ETH-BTC",
+///     "changes": [
+///         ["buy", "0.07140100", "23.30750000"],
+///         ["sell", "0.07143800", "0.00000000"],...
+///     ],
+///     "time": "2023-01-23T12:44:40.557382Z"
+/// }
+/// ```
+///
+/// Unsubscribe messages stop updates until the next subscribe.
+/// Other product subscriptions are rejected with an `error` message.
+pub struct MockCoinbase {
+    data: Arc<RwLock<OrderBook>>,
+    drop_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    drop_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+}
+
+impl MockCoinbase {
+    pub fn start() -> Self {
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let drop_next = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let subscriptions = Arc::<AtomicU64>::default();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let app = Router::new()
+            .route("/", get(ws_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                drop_next: Arc::clone(&drop_next),
+                connections: Arc::clone(&connections),
+                subscriptions: Arc::clone(&subscriptions),
+            });
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+
+        tokio::spawn(async move {
+            eprintln!("MockCoinbase listening on {}", server.local_addr());
+            server.await.unwrap();
+        });
+
+        Self {
+            data,
+            drop_next,
+            connections,
+            subscriptions,
+            port,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://localhost:{}", self.port)
+    }
+
+    /// Update order book data. Will be sent on the next websocket update.
+    pub fn set_orders(&self, book: OrderBook) {
+        *self.data.write().unwrap() = book;
+    }
+
+    /// Drops the next update with changes, as if lost.
+    pub fn drop_next_update(&self) {
+        self.drop_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Returns `true` if an update drop is pending.
+    pub fn drop_pending(&self) -> bool {
+        self.drop_next.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of successful level2 subscriptions received.
+    pub fn subscriptions(&self) -> u64 {
+        self.subscriptions.load(atomic::Ordering::SeqCst)
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn connect_ws(mut ws: WebSocket, shared: Shared) {
+    eprintln!("MockCoinbase new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+
+    // book as of the last published message, `None` if unsubscribed
+    let mut published: Option<OrderBook> = None;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => {
+                let json = match msg {
+                    Some(Ok(Message::Text(json))) => json,
+                    Some(Ok(_)) => continue,
+                    _ => break, // connection closed
+                };
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) else {
+                    continue;
+                };
+                let mut responses = vec![];
+                match json["type"].as_str() {
+                    Some("subscribe") if json["product_ids"][0] != "ETH-BTC" => {
+                        let reason = format!("{} is not a valid product", json["product_ids"][0]);
+                        responses.push(serde_json::json!({
+                            "type": "error",
+                            "message": "Failed to subscribe",
+                            "reason": reason,
+                        }));
+                    }
+                    Some("subscribe") => {
+                        shared.subscriptions.fetch_add(1, atomic::Ordering::SeqCst);
+                        let book = shared.data.read().unwrap().clone();
+                        responses.push(subscriptions(&["ETH-BTC"]));
+                        responses.push(serde_json::json!({
+                            "type": "snapshot",
+                            "product_id": "ETH-BTC",
+                            "bids": book.bids.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+                            "asks": book.asks.iter().map(|o| o.as_array()).collect::<Vec<_>>(),
+                        }));
+                        published = Some(book);
+                    }
+                    Some("unsubscribe") => {
+                        published = None;
+                        responses.push(subscriptions(&[]));
+                    }
+                    _ => continue,
+                }
+                for response in responses {
+                    if ws.send(Message::Text(response.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = interval.tick() => {
+                let Some(prev) = &mut published else {
+                    continue;
+                };
+                let msg = {
+                    let book = shared.data.read().unwrap();
+                    let bids = book.bids_diff(prev);
+                    let asks = book.asks_diff(prev);
+                    let changed = !bids.is_empty() || !asks.is_empty();
+                    let changes: Vec<_> = bids
+                        .into_iter()
+                        .map(|[price, size]| ["buy", price, size])
+                        .chain(asks.into_iter().map(|[price, size]| ["sell", price, size]))
+                        .collect();
+                    let msg = serde_json::json!({
+                        "type": "l2update",
+                        "product_id": "ETH-BTC",
+                        "changes": changes,
+                        "time": humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+                    });
+                    *prev = book.clone();
+                    if changed && shared.drop_next.swap(false, atomic::Ordering::SeqCst) {
+                        continue;
+                    }
+                    msg
+                };
+                if ws.send(Message::Text(msg.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    eprintln!("MockCoinbase connection closed");
+}
+
+/// Current subscriptions response.
+fn subscriptions(product_ids: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "type": "subscriptions",
+        "channels": [{"name": "level2_batch", "product_ids": product_ids}],
+    })
+}
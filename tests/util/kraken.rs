@@ -0,0 +1,318 @@
+use crate::util::{Order, OrderBook};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Localhost mock kraken v2 ws server. Sends book updates every ~100ms.
+///
+/// Subscribe with message
+/// `{"method":"subscribe","params":{"channel":"book","symbol":["ETH/BTC"]}}`,
+/// a snapshot is sent followed by updates of changed levels.
+/// Updates include a `timestamp`, snapshots do not. Prices & quantities are sent as json floats
+/// without trailing zeros, e.g. `2.5`, while checksums use 8 decimals.
+///
+/// Subscribing to the `instrument` channel sends a snapshot with the pair precisions.
+///
+/// # Example message
+/// ```json
+/// {
+///     "channel": "book",
+///     "type": "update",
+///     "data": [
+///         {
+///             "symbol": "ETH/BTC",
+///             "bids": [
+///                 {
+///                     "price": 0.071401,
+///                     "qty": 23.3075
+///                 },...
+///             ],
+///             "asks": [
+///                 {
+///                     "price": 0.071438,
+///                     "qty": 0
+///                 },...
+///             ],
+///             "checksum": 2439117997,
+///             "timestamp": "2023-01-23T12:44:40.557382Z"
+///         }
+///     ]
+/// }
+/// ```
+///
+/// Unsubscribe messages stop updates until the next subscribe.
+/// Other symbol subscriptions are rejected with a `"success":false` response.
+pub struct MockKraken {
+    data: Arc<RwLock<OrderBook>>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+}
+
+impl MockKraken {
+    pub fn start() -> Self {
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let corrupt_next = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let subscriptions = Arc::<AtomicU64>::default();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let app = Router::new()
+            .route("/v2", get(ws_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                corrupt_next: Arc::clone(&corrupt_next),
+                connections: Arc::clone(&connections),
+                subscriptions: Arc::clone(&subscriptions),
+            });
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+
+        tokio::spawn(async move {
+            eprintln!("MockKraken listening on {}", server.local_addr());
+            server.await.unwrap();
+        });
+
+        Self {
+            data,
+            corrupt_next,
+            connections,
+            subscriptions,
+            port,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://localhost:{}/v2", self.port)
+    }
+
+    /// Update order book data. Will be sent on the next websocket update.
+    pub fn set_orders(&self, book: OrderBook) {
+        *self.data.write().unwrap() = book;
+    }
+
+    /// Sends the next update with an incorrect checksum.
+    pub fn corrupt_next_checksum(&self) {
+        self.corrupt_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of successful book subscriptions received.
+    pub fn subscriptions(&self) -> u64 {
+        self.subscriptions.load(atomic::Ordering::SeqCst)
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn connect_ws(mut ws: WebSocket, shared: Shared) {
+    eprintln!("MockKraken new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+
+    // book as of the last published message, `None` if unsubscribed
+    let mut published: Option<OrderBook> = None;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => {
+                let json = match msg {
+                    Some(Ok(Message::Text(json))) => json,
+                    Some(Ok(_)) => continue,
+                    _ => break, // connection closed
+                };
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) else {
+                    continue;
+                };
+                if json["params"]["channel"] == "instrument" {
+                    let mut responses = vec![instrument_response(&json)];
+                    if json["method"] == "subscribe" {
+                        responses.push(instruments());
+                    }
+                    for response in responses {
+                        if ws.send(Message::Text(response.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                if json["params"]["channel"] != "book" {
+                    continue;
+                }
+                let response = match json["method"].as_str() {
+                    Some("subscribe") if json["params"]["symbol"][0] != "ETH/BTC" => {
+                        serde_json::json!({
+                            "method": "subscribe",
+                            "success": false,
+                            "error": "Currency pair not supported",
+                        })
+                    }
+                    Some("subscribe") => {
+                        shared.subscriptions.fetch_add(1, atomic::Ordering::SeqCst);
+                        let book = shared.data.read().unwrap().clone();
+                        let snapshot =
+                            book_message("snapshot", &book.bids, &book.asks, checksum(&book));
+                        let subscribed = subscribed("subscribe").to_string();
+                        if ws.send(Message::Text(subscribed)).await.is_err()
+                            || ws.send(Message::Text(snapshot)).await.is_err()
+                        {
+                            break;
+                        }
+                        published = Some(book);
+                        continue;
+                    }
+                    Some("unsubscribe") => {
+                        published = None;
+                        subscribed("unsubscribe")
+                    }
+                    _ => continue,
+                };
+                if ws.send(Message::Text(response.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            _ = interval.tick() => {
+                let Some(prev) = &mut published else {
+                    continue;
+                };
+                let msg = {
+                    let book = shared.data.read().unwrap();
+                    let mut checksum = checksum(&book);
+                    if shared.corrupt_next.swap(false, atomic::Ordering::SeqCst) {
+                        checksum = checksum.wrapping_add(1);
+                    }
+                    let msg = book_message(
+                        "update",
+                        &diff_orders(book.bids_diff(prev)),
+                        &diff_orders(book.asks_diff(prev)),
+                        checksum,
+                    );
+                    *prev = book.clone();
+                    msg
+                };
+                if ws.send(Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    eprintln!("MockKraken connection closed");
+}
+
+/// Successful method response.
+fn subscribed(method: &str) -> serde_json::Value {
+    serde_json::json!({
+        "method": method,
+        "result": {"channel": "book", "symbol": "ETH/BTC", "depth": 10},
+        "success": true,
+    })
+}
+
+/// Successful instrument channel method response.
+fn instrument_response(request: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "method": request["method"],
+        "result": {"channel": "instrument", "snapshot": true},
+        "success": true,
+    })
+}
+
+/// Instrument snapshot of supported pairs.
+fn instruments() -> serde_json::Value {
+    serde_json::json!({
+        "channel": "instrument",
+        "type": "snapshot",
+        "data": {
+            "assets": [],
+            "pairs": [
+                {"symbol": "BTC/USD", "price_precision": 1, "qty_precision": 8},
+                {"symbol": "ETH/BTC", "price_precision": 8, "qty_precision": 8},
+            ],
+        },
+    })
+}
+
+fn diff_orders(levels: Vec<[&str; 2]>) -> Vec<Order> {
+    levels.into_iter().map(Order::from).collect()
+}
+
+/// Book message with levels as json floats, without trailing zeros.
+///
+/// Only updates include a `timestamp`, as with kraken.
+fn book_message(kind: &str, bids: &[Order], asks: &[Order], checksum: u32) -> String {
+    let levels = |levels: &[Order]| {
+        let levels: Vec<_> = levels
+            .iter()
+            .map(|o| {
+                format!(
+                    r#"{{"price":{},"qty":{}}}"#,
+                    float(&o.price),
+                    float(&o.amount)
+                )
+            })
+            .collect();
+        format!("[{}]", levels.join(","))
+    };
+    let timestamp = match kind {
+        "update" => format!(
+            r#","timestamp":"{}""#,
+            humantime::format_rfc3339_micros(SystemTime::now())
+        ),
+        _ => String::new(),
+    };
+    format!(
+        r#"{{"channel":"book","type":"{kind}","data":[{{"symbol":"ETH/BTC","bids":{},"asks":{},"checksum":{checksum}{timestamp}}}]}}"#,
+        levels(bids),
+        levels(asks),
+    )
+}
+
+/// Returns a decimal as a float would be serialized, e.g. `2.50000000` -> `2.5`.
+fn float(value: &str) -> &str {
+    match value.contains('.') {
+        true => value.trim_end_matches('0').trim_end_matches('.'),
+        false => value,
+    }
+}
+
+/// CRC32 of the top 10 asks then bids, with `.` & leading zeros removed.
+fn checksum(book: &OrderBook) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for order in book.asks.iter().take(10).chain(book.bids.iter().take(10)) {
+        for value in [&order.price, &order.amount] {
+            hasher.update(value.replace('.', "").trim_start_matches('0').as_bytes());
+        }
+    }
+    hasher.finalize()
+}
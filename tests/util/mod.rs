@@ -1,7 +1,19 @@
-use std::time::Duration;
+#![allow(dead_code)] // not all utils are used by every test
+
+use merged_order_book_protos::orderbook_aggregator_client::OrderbookAggregatorClient;
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+use tonic::transport::Channel;
 
 pub mod binance;
+pub mod bitfinex;
 pub mod bitstamp;
+pub mod bybit;
+pub mod coinbase;
+pub mod kraken;
+pub mod okx;
 
 /// A generally "long enough" time to wait for an async thing to have happened.
 pub const TEST_WAIT: Duration = Duration::from_secs(4);
@@ -14,13 +26,77 @@ pub async fn random_open_port() -> u16 {
     addr.local_addr().unwrap().port()
 }
 
-#[derive(Debug, Default)]
+#[allow(unused_macros)]
+macro_rules! assert_level_eq {
+    ($lvl:expr, $name:expr, $price:expr, $amount:expr) => {{
+        let lvl = &$lvl;
+        assert_eq!(lvl.exchange, $name);
+        approx::assert_relative_eq!(lvl.price, $price);
+        approx::assert_relative_eq!(lvl.amount, $amount);
+    }};
+}
+
+/// Starts the grpc server on a random port & returns a connected client.
+///
+/// Exchange urls etc should be configured with env vars before calling.
+pub async fn start_server() -> OrderbookAggregatorClient<Channel> {
+    _ = tracing_subscriber::fmt().with_test_writer().try_init();
+    let grpc_port = random_open_port().await;
+    env::set_var("GRPC_PORT", grpc_port.to_string());
+    env::set_var("METRICS_PORT", random_open_port().await.to_string());
+    tokio::spawn(async {
+        if let Err(err) = merged_order_book::start().await {
+            eprintln!("{err}");
+        }
+    });
+
+    // await a grpc connection
+    let a = Instant::now();
+    loop {
+        let c = OrderbookAggregatorClient::connect(format!("http://localhost:{grpc_port}")).await;
+        if let Ok(client) = c {
+            break client;
+        }
+        assert!(a.elapsed() < TEST_WAIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct OrderBook {
     pub bids: Vec<Order>,
     pub asks: Vec<Order>,
 }
 
-#[derive(Debug)]
+impl OrderBook {
+    /// Returns bid `[price, amount]` changes since `prev`, removed levels have a zero amount.
+    pub fn bids_diff<'a>(&'a self, prev: &'a OrderBook) -> Vec<[&'a str; 2]> {
+        levels_diff(&self.bids, &prev.bids)
+    }
+
+    /// Returns ask `[price, amount]` changes since `prev`, removed levels have a zero amount.
+    pub fn asks_diff<'a>(&'a self, prev: &'a OrderBook) -> Vec<[&'a str; 2]> {
+        levels_diff(&self.asks, &prev.asks)
+    }
+}
+
+fn levels_diff<'a>(new: &'a [Order], prev: &'a [Order]) -> Vec<[&'a str; 2]> {
+    let removed = prev
+        .iter()
+        .filter(|p| !new.iter().any(|n| n.price == p.price))
+        .map(|p| [p.price.as_str(), "0.00000000"]);
+    new.iter()
+        .filter(|n| {
+            !prev
+                .iter()
+                .any(|p| p.price == n.price && p.amount == n.amount)
+        })
+        .map(Order::as_array)
+        .chain(removed)
+        .collect()
+}
+
+#[derive(Debug, Clone)]
 pub struct Order {
     pub price: String,
     pub amount: String,
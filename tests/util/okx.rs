@@ -0,0 +1,263 @@
+use crate::util::OrderBook;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Localhost mock okx public ws server. Sends books updates every ~100ms.
+///
+/// Subscribe with message
+/// `{"op":"subscribe","args":[{"channel":"books","instId":"ETH-BTC"}]}`,
+/// a snapshot is sent followed by updates of changed levels.
+///
+/// # Example message
+/// ```json
+/// {
+///     "arg": {"channel": "books", "instId": "ETH-BTC"},
+///     "action": "update",
+///     "data": [
+///         {
+///             "bids": [["0.07140100", "23.30750000", "0", "1"],...],
+///             "asks": [["0.07143800", "0", "0", "0"],...],
+///             "ts": "1674477880557",
+///             "checksum": -855196043,
+///             "prevSeqId": 123455,
+///             "seqId": 123456
+///         }
+///     ]
+/// }
+/// ```
+///
+/// Unsubscribe messages stop updates until the next subscribe.
+/// Other instrument subscriptions are rejected with an `error` event.
+pub struct MockOkx {
+    data: Arc<RwLock<OrderBook>>,
+    drop_next: Arc<AtomicBool>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct Shared {
+    data: Arc<RwLock<OrderBook>>,
+    drop_next: Arc<AtomicBool>,
+    corrupt_next: Arc<AtomicBool>,
+    connections: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU64>,
+}
+
+impl MockOkx {
+    pub fn start() -> Self {
+        let data = Arc::<RwLock<OrderBook>>::default();
+        let drop_next = Arc::<AtomicBool>::default();
+        let corrupt_next = Arc::<AtomicBool>::default();
+        let connections = Arc::<AtomicU64>::default();
+        let subscriptions = Arc::<AtomicU64>::default();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let app = Router::new()
+            .route("/ws/v5/public", get(ws_handler))
+            .with_state(Shared {
+                data: Arc::clone(&data),
+                drop_next: Arc::clone(&drop_next),
+                corrupt_next: Arc::clone(&corrupt_next),
+                connections: Arc::clone(&connections),
+                subscriptions: Arc::clone(&subscriptions),
+            });
+        let server = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+
+        tokio::spawn(async move {
+            eprintln!("MockOkx listening on {}", server.local_addr());
+            server.await.unwrap();
+        });
+
+        Self {
+            data,
+            drop_next,
+            corrupt_next,
+            connections,
+            subscriptions,
+            port,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://localhost:{}/ws/v5/public", self.port)
+    }
+
+    /// Update order book data. Will be sent on the next websocket update.
+    pub fn set_orders(&self, book: OrderBook) {
+        *self.data.write().unwrap() = book;
+    }
+
+    /// Drops the next update, causing a `seqId` gap.
+    pub fn drop_next_update(&self) {
+        self.drop_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Sends the next update with an incorrect checksum.
+    pub fn corrupt_next_checksum(&self) {
+        self.corrupt_next.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Number of websocket connections received.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Number of successful books subscriptions received.
+    pub fn subscriptions(&self) -> u64 {
+        self.subscriptions.load(atomic::Ordering::SeqCst)
+    }
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(shared): State<Shared>) -> impl IntoResponse {
+    ws.on_upgrade(|ws| connect_ws(ws, shared))
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn connect_ws(mut ws: WebSocket, shared: Shared) {
+    eprintln!("MockOkx new connection");
+    shared.connections.fetch_add(1, atomic::Ordering::SeqCst);
+
+    // book as of the last published message, `None` if unsubscribed
+    let mut published: Option<OrderBook> = None;
+    let mut seq_id = 1000;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => {
+                let json = match msg {
+                    Some(Ok(Message::Text(json))) => json,
+                    Some(Ok(_)) => continue,
+                    _ => break, // connection closed
+                };
+                let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) else {
+                    continue;
+                };
+                let arg = &json["args"][0];
+                let mut responses = vec![];
+                match json["op"].as_str() {
+                    Some("subscribe") if arg["instId"] != "ETH-BTC" => {
+                        let msg = format!("Instrument ID doesn't exist:{}", arg["instId"]);
+                        responses.push(serde_json::json!({
+                            "event": "error",
+                            "code": "60018",
+                            "msg": msg,
+                        }));
+                    }
+                    Some("subscribe") => {
+                        shared.subscriptions.fetch_add(1, atomic::Ordering::SeqCst);
+                        let book = shared.data.read().unwrap().clone();
+                        responses.push(serde_json::json!({"event": "subscribe", "arg": arg}));
+                        seq_id += 1;
+                        responses.push(books_message("snapshot", &book, &book, -1, seq_id));
+                        published = Some(book);
+                    }
+                    Some("unsubscribe") => {
+                        published = None;
+                        responses.push(serde_json::json!({"event": "unsubscribe", "arg": arg}));
+                    }
+                    _ => continue,
+                }
+                for response in responses {
+                    if ws.send(Message::Text(response.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = interval.tick() => {
+                let Some(prev) = &mut published else {
+                    continue;
+                };
+                let prev_seq_id = seq_id;
+                seq_id += 1;
+                let msg = {
+                    let book = shared.data.read().unwrap();
+                    let mut msg = books_message("update", &book, prev, prev_seq_id, seq_id);
+                    if shared.corrupt_next.swap(false, atomic::Ordering::SeqCst) {
+                        msg["data"][0]["checksum"] = 42.into();
+                    }
+                    *prev = book.clone();
+                    if shared.drop_next.swap(false, atomic::Ordering::SeqCst) {
+                        continue;
+                    }
+                    msg
+                };
+                if ws.send(Message::Text(msg.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    eprintln!("MockOkx connection closed");
+}
+
+/// Books snapshot of all levels, or update of the changes since `prev`.
+fn books_message(
+    action: &str,
+    book: &OrderBook,
+    prev: &OrderBook,
+    prev_seq_id: i64,
+    seq_id: i64,
+) -> serde_json::Value {
+    let levels = |levels: Vec<[&str; 2]>| -> Vec<[String; 4]> {
+        levels
+            .into_iter()
+            .map(|[price, size]| [price.into(), size.into(), "0".into(), "1".into()])
+            .collect()
+    };
+    let (bids, asks) = match action {
+        "snapshot" => (
+            book.bids.iter().map(|o| o.as_array()).collect(),
+            book.asks.iter().map(|o| o.as_array()).collect(),
+        ),
+        _ => (book.bids_diff(prev), book.asks_diff(prev)),
+    };
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    serde_json::json!({
+        "arg": {"channel": "books", "instId": "ETH-BTC"},
+        "action": action,
+        "data": [{
+            "bids": levels(bids),
+            "asks": levels(asks),
+            "ts": ts.as_millis().to_string(),
+            "checksum": checksum(book),
+            "prevSeqId": prev_seq_id,
+            "seqId": seq_id,
+        }],
+    })
+}
+
+/// Signed CRC32 of the top 25 bids & asks interleaved, `bid:size:ask:size:...`.
+fn checksum(book: &OrderBook) -> i32 {
+    let mut values = vec![];
+    for idx in 0..25 {
+        for order in [book.bids.get(idx), book.asks.get(idx)]
+            .into_iter()
+            .flatten()
+        {
+            values.push(order.price.as_str());
+            values.push(order.amount.as_str());
+        }
+    }
+    crc32fast::hash(values.join(":").as_bytes()) as i32
+}
@@ -8,7 +8,7 @@ use axum::{
     Router,
 };
 use merged_order_book_protos::{
-    orderbook_aggregator_client::OrderbookAggregatorClient, Empty, Level,
+    orderbook_aggregator_client::OrderbookAggregatorClient, BookSummaryRequest, Level,
 };
 use std::net::SocketAddr;
 use tonic::transport::Channel;
@@ -47,7 +47,7 @@ async fn ws_handler(
 
 /// Actual websocket statemachine (one will be spawned per connection)
 async fn connect_ws(mut ws: WebSocket, mut client: OrderbookAggregatorClient<Channel>) {
-    let mut stream = match client.book_summary(Empty {}).await {
+    let mut stream = match client.book_summary(BookSummaryRequest::default()).await {
         Ok(s) => s.into_inner(),
         Err(err) => {
             eprintln!("{err}");